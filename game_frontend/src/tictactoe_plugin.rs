@@ -1,6 +1,8 @@
 use bevy::{ecs::schedule::ShouldRun, prelude::*};
 
-use minimax::{connect4::Connect4Game, game::*, minimax::*, tictactoe::TicTacToeGame};
+use minimax::{
+    connect4::Connect4Game, engine::SearchEngine, game::*, minimax::*, tictactoe::TicTacToeGame,
+};
 
 pub struct TicTacToeGamePlugin;
 
@@ -38,11 +40,13 @@ fn make_move(
     mut state_changed_event: EventWriter<GameStateChangedEvent>,
     mut game: ResMut<GameResource>,
 ) {
-    let mut minimax = Minimax::new(MinimaxParams::default()); // TODO should reuse
-    let best_move = minimax.minimax(&game.0).best_move;
+    // any engine works here: Mcts, RandomAgent, GreedyAgent...
+    let mut engine: Box<dyn SearchEngine<TicTacToeGame>> =
+        Box::new(Minimax::new(MinimaxParams::default())); // TODO should reuse
+    let best_move = engine.choose_move(&game.0);
     println!("Best move: {:?}", best_move);
     if let Some(best_move) = best_move {
-        game.0.apply_move(best_move);
+        game.0.make_move(best_move);
         let winner = game.0.get_winner();
         if winner == Player::None {
             state_changed_event.send(GameStateChangedEvent);
@@ -1,5 +1,7 @@
-use criterion::{black_box, criterion_group, criterion_main, Criterion};
-use minimax::{connect4::Connect4Game, game::Player, minimax::*, tictactoe::TicTacToeGame}; // TODO minimax::minimax::minimax is funny, need better names
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use minimax::{
+    connect4::Connect4Game, dynamic::BoxedGame, game::Player, minimax::*, tictactoe::TicTacToeGame,
+}; // TODO minimax::minimax::minimax is funny, need better names
 
 fn tictactoe_benchmark(c: &mut Criterion) {
     let board_str = "
@@ -7,12 +9,32 @@ fn tictactoe_benchmark(c: &mut Criterion) {
 ...
 ...";
     let game = TicTacToeGame::from_state(board_str, Player::X);
+    let mut group = c.benchmark_group("tictactoe_full_game");
+    for (name, in_place_moves, move_hints) in [
+        ("copy", false, true),
+        ("in_place", true, true),
+        ("eager", true, false),
+    ] {
+        let mut minimax = Minimax::new(MinimaxParams {
+            in_place_moves,
+            move_hints,
+            ..Default::default()
+        });
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                minimax.minimax(black_box(&game));
+            })
+        });
+    }
+    // same search through the dyn adapter
+    let boxed_game: BoxedGame<_> = Box::new(game);
     let mut minimax = Minimax::new(MinimaxParams::default());
-    c.bench_function("tictactoe_full_game", |b| {
+    group.bench_function("dyn", |b| {
         b.iter(|| {
-            minimax.minimax(black_box(&game));
+            minimax.minimax(black_box(&boxed_game));
         })
     });
+    group.finish();
 }
 
 fn connect_benchmark(c: &mut Criterion) {
@@ -24,16 +46,114 @@ fn connect_benchmark(c: &mut Criterion) {
         .......
         .......";
     let game = Connect4Game::from_state(board_str, None, Player::X);
+    let mut group = c.benchmark_group("connect4_full_game");
+    for (name, in_place_moves, move_hints) in [
+        ("copy", false, true),
+        ("in_place", true, true),
+        ("eager", true, false),
+    ] {
+        let mut minimax = Minimax::new(MinimaxParams {
+            max_depth: 10,
+            in_place_moves,
+            move_hints,
+            ..Default::default()
+        });
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                minimax.minimax(black_box(&game));
+            })
+        });
+    }
+    let boxed_game: BoxedGame<_> = Box::new(game);
+    let mut minimax = Minimax::new(MinimaxParams {
+        max_depth: 10,
+        ..Default::default()
+    });
+    group.bench_function("dyn", |b| {
+        b.iter(|| {
+            minimax.minimax(black_box(&boxed_game));
+        })
+    });
+    group.finish();
+}
+
+fn connect4_algorithms_benchmark(c: &mut Criterion) {
+    let board_str = "
+        .......
+        .......
+        .......
+        .......
+        .......
+        .......";
+    let game = Connect4Game::from_state(board_str, None, Player::X);
+    let mut group = c.benchmark_group("connect4_algorithms");
+    // MTD(f) always has a table of its own, so alpha beta gets compared with and without the cache
+    for (name, algorithm, cache_enabled) in [
+        ("alpha_beta", SearchAlgorithm::Minimax, false),
+        ("alpha_beta_cache", SearchAlgorithm::Minimax, true),
+        ("negamax_pvs", SearchAlgorithm::NegamaxPvs, true),
+        ("mtdf", SearchAlgorithm::Mtdf, true),
+    ] {
+        // a new search every time, the cache would remember the result otherwise
+        let new_minimax = || {
+            Minimax::new(MinimaxParams {
+                max_depth: 10,
+                algorithm,
+                cache_enabled,
+                ..Default::default()
+            })
+        };
+        group.bench_function(name, |b| {
+            b.iter_batched(
+                new_minimax,
+                |mut minimax| minimax.minimax(black_box(&game)),
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+fn connect4_parallel_benchmark(c: &mut Criterion) {
+    let board_str = "
+        .......
+        .......
+        .......
+        .......
+        .......
+        .......";
+    let game = Connect4Game::from_state(board_str, None, Player::X);
+    let mut group = c.benchmark_group("connect4_parallel");
     let mut minimax = Minimax::new(MinimaxParams {
         max_depth: 10,
         ..Default::default()
     });
-    c.bench_function("connect4_full_game", |b| {
+    group.bench_function("sequential", |b| {
         b.iter(|| {
             minimax.minimax(black_box(&game));
         })
     });
+    // speedup depends on the cores of the machine running the benchmark
+    for threads in [1, 2, 4, 8] {
+        let mut minimax = Minimax::new(MinimaxParams {
+            max_depth: 10,
+            threads,
+            ..Default::default()
+        });
+        group.bench_function(format!("threads_{}", threads), |b| {
+            b.iter(|| {
+                minimax.minimax_parallel(black_box(&game));
+            })
+        });
+    }
+    group.finish();
 }
 
-criterion_group!(benches, tictactoe_benchmark, connect_benchmark);
+criterion_group!(
+    benches,
+    tictactoe_benchmark,
+    connect_benchmark,
+    connect4_algorithms_benchmark,
+    connect4_parallel_benchmark
+);
 criterion_main!(benches);
@@ -1,8 +1,11 @@
 use iai::black_box;
 use minimax::{connect4::Connect4Game, game::Player, minimax::*, tictactoe::TicTacToeGame};
 
-fn tictactoe_benchmark() {
-    let mut minimax = Minimax::new(MinimaxParams::default());
+fn tictactoe(in_place_moves: bool) {
+    let mut minimax = Minimax::new(MinimaxParams {
+        in_place_moves,
+        ..Default::default()
+    });
     let board_str = "
         ...
         ...
@@ -11,9 +14,10 @@ fn tictactoe_benchmark() {
     minimax.minimax(black_box(&game));
 }
 
-fn connect4_benchmark() {
+fn connect4(in_place_moves: bool) {
     let mut minimax = Minimax::new(MinimaxParams {
         max_depth: 10,
+        in_place_moves,
         ..Default::default()
     });
     let board_str = "
@@ -27,4 +31,25 @@ fn connect4_benchmark() {
     minimax.minimax(black_box(&game));
 }
 
-iai::main!(tictactoe_benchmark, connect4_benchmark);
+fn tictactoe_benchmark() {
+    tictactoe(false)
+}
+
+fn tictactoe_in_place_benchmark() {
+    tictactoe(true)
+}
+
+fn connect4_benchmark() {
+    connect4(false)
+}
+
+fn connect4_in_place_benchmark() {
+    connect4(true)
+}
+
+iai::main!(
+    tictactoe_benchmark,
+    tictactoe_in_place_benchmark,
+    connect4_benchmark,
+    connect4_in_place_benchmark
+);
@@ -1,32 +1,98 @@
-use std::{
-    collections::{HashMap, HashSet},
-    fmt::Debug,
-    iter::repeat,
-};
+use std::{collections::HashSet, fmt::Debug, iter::repeat, sync::OnceLock};
 
-use crate::{game::*, minimax::*};
+use crate::{game::*, minimax::*, zobrist::ZobristTable};
 
 const WIDTH: usize = 7;
 const HEIGHT: usize = 6;
 
+/// Index of the column to drop the piece in
+pub type Move = usize;
+/// Row and column of a cell on the board
+pub type Position = (usize, usize);
+
 #[derive(Clone)]
 pub struct Connect4Game {
-    pub current_player: Player,
-    pub board: BoardType,
-    last_move: Option<Move>,
+    current_player: Player,
+    board: BoardType,
+    last_move: Option<Position>,
+    /// Columns played so far, needed to restore the last move when unmaking moves
+    history: [u8; WIDTH * HEIGHT],
+    history_len: usize,
+    /// Incremental Zobrist hash. Private like the board, so only the moves can change either
+    hash: GameHash,
+}
+
+fn zobrist() -> &'static ZobristTable<GameHash> {
+    static KEYS: OnceLock<ZobristTable<GameHash>> = OnceLock::new();
+    KEYS.get_or_init(|| ZobristTable::new(WIDTH * HEIGHT, 2, 4))
 }
 
 impl Connect4Game {
     /// Does not validate if the state is correct or reachable (ie might have board filled with X)
-    pub fn from_state(board_str: &str, last_move: Option<Move>, current_player: Player) -> Self {
-        let mut game = Connect4Game::default();
-        game.current_player = current_player;
-        game.last_move = last_move;
+    pub fn from_state(
+        board_str: &str,
+        last_move: Option<Position>,
+        current_player: Player,
+    ) -> Self {
+        let mut game = Connect4Game {
+            current_player,
+            last_move,
+            ..Default::default()
+        };
+        if let Some((_, column)) = last_move {
+            game.push_history(column);
+        }
         let board_chars = board_str.chars().filter(|c| !c.is_whitespace());
         itertools::iproduct!(0..HEIGHT, 0..WIDTH)
             .zip(board_chars)
             .for_each(|((i, j), c)| game.board.set(i, j, Player::from(c)));
-        return game;
+        game.hash = zobrist().hash_board(&game.board, current_player);
+        game
+    }
+
+    /// Piece in the cell, row 0 is the top of the board
+    pub fn cell(&self, row: usize, column: usize) -> Player {
+        self.board.get(row, column)
+    }
+
+    /// Position where a piece dropped in the column would land, if the column is not full
+    fn drop_position(&self, column: Move) -> Option<Position> {
+        (0..HEIGHT)
+            .rev()
+            .zip(repeat(column))
+            .find(|(i, j)| self.board.get(*i, *j) == Player::None)
+    }
+
+    /// Position of the topmost piece in the column, if there is any
+    fn top_position(&self, column: Move) -> Option<Position> {
+        (0..HEIGHT)
+            .zip(repeat(column))
+            .find(|(i, j)| self.board.get(*i, *j) != Player::None)
+    }
+
+    /// True if the player would connect four by dropping a piece in the column
+    fn wins_with(&self, column: Move, player: Player) -> bool {
+        let Some((i, j)) = self.drop_position(column) else {
+            return false;
+        };
+        const DIRECTIONS: [(isize, isize); 4] = [(0, 1), (1, 1), (1, 0), (-1, 1)];
+        DIRECTIONS.iter().any(|(di, dj)| {
+            // pieces of the player in a row, going away from the cell in one direction
+            let count = |sign: isize| {
+                (1..4)
+                    .take_while(|k| {
+                        let (row, col) = (i as isize + sign * k * di, j as isize + sign * k * dj);
+                        self.board.get_safe(row, col) == Some(player)
+                    })
+                    .count()
+            };
+            1 + count(1) + count(-1) >= 4
+        })
+    }
+
+    fn push_history(&mut self, column: Move) {
+        self.history[self.history_len] = column as u8;
+        self.history_len += 1;
     }
 
     fn _score(&self, i: usize, j: usize) -> i32 {
@@ -69,7 +135,7 @@ impl Connect4Game {
         if width >= 4 {
             return Some(last_player);
         }
-        return None;
+        None
     }
 }
 
@@ -91,7 +157,7 @@ impl BoardTypeAccess for BoardType {
     }
 
     fn get_safe(&self, i: isize, j: isize) -> Option<Player> {
-        if i < 0 || i >= WIDTH as isize || j < 0 || j >= HEIGHT as isize {
+        if i < 0 || i >= HEIGHT as isize || j < 0 || j >= WIDTH as isize {
             return None;
         }
         Some(self.get(i as usize, j as usize))
@@ -99,8 +165,11 @@ impl BoardTypeAccess for BoardType {
 }
 
 impl MinimaxDriver for Connect4Game {
+    type Move = Move;
+    type Hash = GameHash;
+
     fn get_winner(&self) -> Player {
-        if let Some(_) = self.last_move {
+        if self.last_move.is_some() {
             const DIRECTIONS: [((i32, i32), (i32, i32)); 4] = [
                 ((0, -1), (0, 1)),  // horizontal search
                 ((-1, -1), (1, 1)), // diagonal \
@@ -113,36 +182,44 @@ impl MinimaxDriver for Connect4Game {
                 }
             }
         }
-        return Player::None;
-    }
-
-    fn get_possible_moves(&self) -> Box<dyn Iterator<Item = Move> + '_> {
-        Box::new(
-            (0..WIDTH)
-                .map(|j| {
-                    (0..HEIGHT)
-                        .rev()
-                        .zip(repeat(j))
-                        .find(|(i, j)| self.board.get(*i, *j) == Player::None)
-                })
-                .filter(|&p| p.is_some())
-                .map(|p| p.unwrap()),
-        )
+        Player::None
+    }
+
+    fn get_possible_moves(&self) -> impl Iterator<Item = Move> + '_ {
+        // a column is playable as long as its top cell is empty
+        (0..WIDTH).filter(|&j| self.board.get(0, j) == Player::None)
     }
 
     /// No checks are applied. Assumes that the move has been taken from [`get_possible_moves()`]
-    fn apply_move(&self, next_move: Move) -> Box<dyn MinimaxDriver> {
-        let mut new_game = Box::new(self.clone());
-        new_game
-            .board
-            .set(next_move.0, next_move.1, self.current_player);
-        new_game.current_player = self.current_player.next();
-        new_game.last_move = Some(next_move);
-        return new_game;
+    fn make_move(&mut self, next_move: Move) {
+        let position = self.drop_position(next_move).unwrap();
+        self.board.set(position.0, position.1, self.current_player);
+        self.hash ^= zobrist().player(position.0 * WIDTH + position.1, self.current_player)
+            ^ zobrist().side_to_move();
+        self.current_player = self.current_player.next();
+        self.last_move = Some(position);
+        self.push_history(next_move);
+    }
+
+    fn unmake_move(&mut self, last_move: Move) {
+        let position = self.top_position(last_move).unwrap();
+        self.current_player = self.current_player.next();
+        self.board.set(position.0, position.1, Player::None);
+        self.hash ^= zobrist().player(position.0 * WIDTH + position.1, self.current_player)
+            ^ zobrist().side_to_move();
+        self.history_len -= 1;
+        self.last_move = match self.history_len {
+            0 => None,
+            len => self.top_position(self.history[len - 1] as Move),
+        };
     }
 
     fn get_hash(&self) -> GameHash {
-        // let grid_values = self.board.iter().flat_map(|r| r.iter().map(|p| p));
+        self.hash
+    }
+
+    /// Exact encoding of the board, no collisions possible
+    fn get_verification_hash(&self) -> Option<GameHash> {
         let mut hash: u128 = self
             .board
             .iter()
@@ -150,7 +227,25 @@ impl MinimaxDriver for Connect4Game {
             .map(|(val, pos)| (*val as u128) * 4u128.pow(pos))
             .sum();
         hash += self.current_player as u128;
-        hash
+        Some(hash)
+    }
+
+    /// Center columns first, they are part of more lines
+    fn move_order_hint(&self, next_move: Move) -> Option<Score> {
+        Some(-(next_move as Score - (WIDTH / 2) as Score).abs())
+    }
+
+    /// Someone can win with the next piece
+    fn is_unstable(&self) -> bool {
+        !self.has_ended()
+            && (0..WIDTH).any(|column| {
+                self.wins_with(column, Player::X) || self.wins_with(column, Player::O)
+            })
+    }
+
+    /// Blocking a win of the opponent
+    fn is_forcing(&self, next_move: Move) -> bool {
+        self.wins_with(next_move, self.current_player.next())
     }
 
     fn get_current_player(&self) -> Player {
@@ -166,9 +261,9 @@ impl MinimaxDriver for Connect4Game {
         // TODO this part is horrible, should refactor after it's working
         // this should replace winner function when done
         let mut score = 0;
-        let mut threats: [HashSet<Move>; 3] = Default::default();
+        let mut threats: [HashSet<Position>; 3] = Default::default();
 
-        let direction_iterators: Vec<Box<dyn Iterator<Item = ((isize, isize), (isize, isize))>>> = vec![
+        let direction_iterators: Vec<Box<SweepIterator>> = vec![
             // sweep right
             Box::new(
                 (0..HEIGHT as isize)
@@ -193,7 +288,7 @@ impl MinimaxDriver for Connect4Game {
             // sweep diagonal /
             Box::new(
                 repeat(HEIGHT as isize - 1)
-                    .zip(1..WIDTH as isize)
+                    .zip(0..WIDTH as isize)
                     // add some higher positions manually
                     .chain(vec![(4,0),(3,0),(2,0),(1,0)])
                     .zip(repeat((-1, 1))),
@@ -205,7 +300,7 @@ impl MinimaxDriver for Connect4Game {
                 // search for adjacent pieces in a sliding window of 4
                 let mut window = WindowCount::default();
                 let mut k = 0;
-                let mut empties: HashSet<Move> = Default::default();
+                let mut empties: HashSet<Position> = Default::default();
                 while let Some(p) = self.board.get_safe(i + k * dir.0, j + k * dir.1) {
                     window.count[p as usize] += 1;
                     if p == Player::None {
@@ -229,7 +324,7 @@ impl MinimaxDriver for Connect4Game {
                         && window.count[Player::None as usize] == 1
                     {
                         let p = empties.iter().next().unwrap();
-                        threats[Player::X as usize].insert(p.clone());
+                        threats[Player::X as usize].insert(*p);
                     } else if window.count[Player::X as usize] == 4 {
                         return EvaluationScore {
                             score: MAX_SCORE,
@@ -240,7 +335,7 @@ impl MinimaxDriver for Connect4Game {
                         && window.count[Player::None as usize] == 1
                     {
                         let p = empties.iter().next().unwrap();
-                        threats[Player::O as usize].insert(p.clone());
+                        threats[Player::O as usize].insert(*p);
                     } else if window.count[Player::O as usize] == 4 {
                         return EvaluationScore {
                             score: -MAX_SCORE,
@@ -248,10 +343,9 @@ impl MinimaxDriver for Connect4Game {
                         };
                     }
                 }
-                
             }
-            score += threats[Player::X as usize].iter().count() as i32 * 10;
-            score -= threats[Player::O as usize].iter().count() as i32 * 10;
+            score += threats[Player::X as usize].len() as i32 * 10;
+            score -= threats[Player::O as usize].len() as i32 * 10;
 
             // let next_move_threats_x = threats[Player::X as usize]
             //     .iter()
@@ -283,7 +377,7 @@ impl MinimaxDriver for Connect4Game {
             // }
         }
         EvaluationScore {
-            score: score,
+            score,
             is_terminal: false,
         }
     }
@@ -308,6 +402,9 @@ impl MinimaxDriver for Connect4Game {
     // }
 }
 
+/// Starting cell and direction of a sweep over the board
+type SweepIterator = dyn Iterator<Item = ((isize, isize), (isize, isize))>;
+
 #[derive(Default)]
 struct WindowCount {
     count: [usize; 3],
@@ -335,15 +432,61 @@ impl Default for Connect4Game {
             current_player: Player::X,
             board: [Player::None; WIDTH * HEIGHT],
             last_move: None,
+            history: [0; WIDTH * HEIGHT],
+            history_len: 0,
+            hash: 0,
         }
     }
 }
 
+/// Win in N puzzles with X to move, from https://sites.math.rutgers.edu/~zeilberg/C4/Introduction.html.
+/// Shared by the tests of the different searches, so they all solve the same positions
+#[cfg(test)]
+pub(crate) mod puzzles {
+    pub(crate) const WIN_IN_TWO: &str = "
+        .......
+        .......
+        .......
+        XX.....
+        XX.OX.O
+        OOOXXOO";
+
+    pub(crate) const WIN_IN_THREE: &str = "
+        .......
+        .......
+        ..X....
+        X.O....
+        O.X....
+        XXOOOXO";
+
+    pub(crate) const WIN_IN_FOUR: &str = "
+        .......
+        .......
+        .......
+        .O..OXO
+        OX.XXXO
+        XO.OXOX";
+
+    pub(crate) const WIN_IN_FIVE: &str = "
+        .......
+        .......
+        .......
+        .....X.
+        .XOOXO.
+        .XXOOXO";
+}
+
 #[cfg(test)]
 mod tests {
+    use std::{
+        cell::RefCell,
+        rc::Rc,
+        time::{Duration, Instant},
+    };
+
     use crate::minimax::Minimax;
 
-    use super::*;
+    use super::{puzzles::*, *};
     use rstest::*;
 
     #[rstest]
@@ -397,7 +540,7 @@ mod tests {
         O O O X X O O",
         Some((4, 2))
     )]
-    fn test_winner_is_detected(#[case] board_str: &str, #[case] last_move: Option<Move>) {
+    fn test_winner_is_detected(#[case] board_str: &str, #[case] last_move: Option<Position>) {
         let game = Connect4Game::from_state(board_str, last_move, crate::game::Player::O);
         println!("{:?}", game);
         assert_eq!(game.get_winner(), Player::X);
@@ -413,11 +556,48 @@ mod tests {
         .OXOXX.
         .OOXXO.";
         let game = Connect4Game::from_state(state, Some((4, 5)), crate::game::Player::O);
-        let mut actual: Vec<Move> = game.get_possible_moves().collect();
-        let mut expected = vec![(5, 0), (0, 1), (1, 2), (0, 3), (2, 4), (3, 5), (5, 6)];
-        actual.sort();
-        expected.sort();
-        assert_eq!(actual, expected);
+        let actual: Vec<Move> = game.get_possible_moves().collect();
+        assert_eq!(actual, vec![0, 1, 2, 3, 4, 5, 6]);
+
+        let state = "
+        .X.....
+        .O.X...
+        .XOO...
+        .OXOX..
+        .OXOXX.
+        .OOXXO.";
+        let game = Connect4Game::from_state(state, Some((0, 1)), crate::game::Player::O);
+        let actual: Vec<Move> = game.get_possible_moves().collect();
+        assert_eq!(actual, vec![0, 2, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn test_make_unmake_move() {
+        let state = "
+        .......
+        .......
+        .......
+        .......
+        .....O.
+        .XXX.OO";
+        let mut game = Connect4Game::from_state(state, Some((4, 5)), Player::X);
+        let original_hash = game.get_hash();
+        game.make_move(6);
+        game.make_move(5);
+        game.make_move(4);
+        assert_eq!(game.get_winner(), Player::X);
+        // incremental hash is the same as hashing the whole board
+        assert_eq!(
+            game.get_hash(),
+            zobrist().hash_board(&game.board, game.current_player)
+        );
+        game.unmake_move(4);
+        assert_eq!(game.last_move, Some((3, 5)));
+        assert_eq!(game.get_winner(), Player::None);
+        game.unmake_move(5);
+        game.unmake_move(6);
+        assert_eq!(game.last_move, Some((4, 5)));
+        assert_eq!(game.get_hash(), original_hash);
     }
 
     // see https://sites.math.rutgers.edu/~zeilberg/C4/Introduction.html
@@ -436,86 +616,455 @@ mod tests {
         .OXOXX.
         .OOXXO.";
         let game = Connect4Game::from_state(state, Some((4, 5)), crate::game::Player::X);
-        let node = minimax.minimax(&game);
-        assert_eq!(node.get_best_move(), Some((2, 4)));
+        let result = minimax.minimax(&game);
+        assert_eq!(result.best_move, Some(4));
     }
 
     // TODO repeat of ttt test. refactor
-    fn play(game: Connect4Game, depth: u32) -> (Box<dyn MinimaxDriver>, i32) {
-        let mut moves = 0;
+    fn play(game: Connect4Game, depth: u32, cache_enabled: bool) -> (Connect4Game, usize) {
         let mut minimax = Minimax::new(MinimaxParams {
             max_depth: depth,
+            cache_enabled,
+            ..Default::default()
+        });
+        let result = minimax.minimax(&game);
+        let line = &result.principal_variation;
+        let moves = line.len();
+        let final_game = apply_moves(&game, line).pop().unwrap_or(game);
+        (final_game, moves)
+    }
+
+    #[rstest]
+    fn test_win_in_two(#[values(false, true)] cache_enabled: bool) {
+        let game = Connect4Game::from_state(WIN_IN_TWO, None, crate::game::Player::X);
+        let (final_game, moves) = play(game, 7, cache_enabled);
+        assert_eq!(final_game.get_winner(), Player::X);
+        assert_eq!(moves, 3);
+    }
+
+    #[rstest]
+    fn test_win_in_three(#[values(false, true)] cache_enabled: bool) {
+        let game = Connect4Game::from_state(WIN_IN_THREE, None, crate::game::Player::X);
+        let (final_game, moves) = play(game, 7, cache_enabled);
+        assert_eq!(final_game.get_winner(), Player::X);
+        assert_eq!(moves, 5);
+    }
+
+    #[rstest]
+    fn test_win_in_four(#[values(false, true)] cache_enabled: bool) {
+        let game = Connect4Game::from_state(WIN_IN_FOUR, None, crate::game::Player::X);
+        let (final_game, moves) = play(game, 9, cache_enabled);
+        assert_eq!(final_game.get_winner(), Player::X);
+        assert_eq!(moves, 7);
+    }
+
+    /// Most search features are checked on it
+    #[fixture]
+    fn win_in_five() -> Connect4Game {
+        Connect4Game::from_state(WIN_IN_FIVE, None, Player::X)
+    }
+
+    /// Alpha-beta without any of the optional features, the other searches must agree with it
+    fn plain_search(game: &Connect4Game, max_depth: u32) -> SearchResult<usize> {
+        Minimax::new(MinimaxParams {
+            max_depth,
+            ..Default::default()
+        })
+        .minimax(game)
+    }
+
+    fn assert_x_wins(game: &Connect4Game, result: &SearchResult<usize>) {
+        let final_game = apply_moves(game, &result.principal_variation).pop();
+        assert_eq!(final_game.unwrap().get_winner(), Player::X);
+    }
+
+    #[rstest]
+    fn test_win_in_five(win_in_five: Connect4Game, #[values(false, true)] cache_enabled: bool) {
+        let (final_game, moves) = play(win_in_five, 9, cache_enabled);
+        assert_eq!(final_game.get_winner(), Player::X);
+        assert_eq!(moves, 9);
+    }
+
+    #[rstest]
+    #[case::win_in_two(WIN_IN_TWO, 7)]
+    #[case::win_in_three(WIN_IN_THREE, 7)]
+    #[case::win_in_four(WIN_IN_FOUR, 9)]
+    #[case::win_in_five(WIN_IN_FIVE, 9)]
+    fn test_search_algorithms(
+        #[case] state: &str,
+        #[case] depth: u32,
+        #[values(SearchAlgorithm::NegamaxPvs, SearchAlgorithm::Mtdf)] algorithm: SearchAlgorithm,
+        #[values(false, true)] cache_enabled: bool,
+    ) {
+        let game = Connect4Game::from_state(state, None, Player::X);
+        let expected = plain_search(&game, depth);
+        let result = Minimax::new(MinimaxParams {
+            max_depth: depth,
+            algorithm,
+            cache_enabled,
+            ..Default::default()
+        })
+        .minimax(&game);
+        assert_eq!(result.score, expected.score);
+        assert_eq!(
+            result.principal_variation.len(),
+            expected.principal_variation.len()
+        );
+        assert_x_wins(&game, &result);
+    }
+
+    #[rstest]
+    fn test_mtdf_own_table(win_in_five: Connect4Game, #[values(false, true)] cache_enabled: bool) {
+        let mut minimax = Minimax::new(MinimaxParams {
+            max_depth: 7,
+            algorithm: SearchAlgorithm::Mtdf,
+            cache_enabled,
             ..Default::default()
         });
-        let mut decision_node = minimax.minimax(&game);
-        let mut new_game = Box::new(game) as Box<dyn MinimaxDriver>;
-        while decision_node.best_move.is_some() {
-            let next_move = decision_node.best_move.unwrap();
-            new_game = new_game.apply_move(next_move);
-            decision_node = decision_node.moves.get(&next_move).unwrap().clone();
-            moves += 1;
+        let first = minimax.minimax(&win_in_five);
+        let second = minimax.minimax(&win_in_five);
+        assert_eq!(second.score, first.score);
+        // the table is used whatever the cache setting, but nothing carries over to the next search
+        assert!(minimax.get_cache_stats().hits > 0);
+        assert_eq!(second.nodes_examined, first.nodes_examined);
+    }
+
+    #[rstest]
+    #[case(true, false, false)]
+    #[case(false, true, false)]
+    #[case(false, false, true)]
+    #[case(true, true, true)]
+    fn test_move_ordering(
+        win_in_five: Connect4Game,
+        #[case] killer_moves: bool,
+        #[case] history_heuristic: bool,
+        #[case] counter_moves: bool,
+    ) {
+        let game = win_in_five;
+        let search = |killer_moves, history_heuristic, counter_moves| {
+            let mut minimax = Minimax::new(MinimaxParams {
+                max_depth: 7,
+                killer_moves,
+                history_heuristic,
+                counter_moves,
+                ..Default::default()
+            });
+            let result = minimax.minimax(&game);
+            (result.score, minimax.get_ordering_stats())
+        };
+        let (expected_score, unordered) = search(false, false, false);
+        let (score, stats) = search(killer_moves, history_heuristic, counter_moves);
+        // ordering changes how much gets pruned, never the result
+        assert_eq!(score, expected_score);
+        assert!(stats.cutoffs > 0);
+        // the move that refutes a node gets tried first more often
+        assert!(stats.cutoff_rate() > unordered.cutoff_rate());
+    }
+
+    #[rstest]
+    #[case::previous_score(None, false)]
+    // the score is 40, inside the window
+    #[case::close_guess(Some(45), false)]
+    #[case::wrong_guess(Some(-500), true)]
+    fn test_aspiration_window(
+        win_in_five: Connect4Game,
+        #[case] score_guess: Option<Score>,
+        #[case] widened: bool,
+        #[values(SearchAlgorithm::Minimax, SearchAlgorithm::NegamaxPvs)] algorithm: SearchAlgorithm,
+    ) {
+        let game = win_in_five;
+        let expected = plain_search(&game, 5);
+        let mut minimax = Minimax::new(MinimaxParams {
+            max_depth: 5,
+            algorithm,
+            aspiration_window: Some(10),
+            score_guess,
+            ..Default::default()
+        });
+        if score_guess.is_none() {
+            // the second search starts from the score of the first one
+            minimax.minimax(&game);
+        }
+        let result = minimax.minimax(&game);
+        let researches = minimax.get_ordering_stats().aspiration_researches;
+        // the window only changes how much gets pruned, never the result
+        assert_eq!(result.score, expected.score);
+        if widened {
+            assert!(researches >= 1);
+        } else {
+            assert_eq!(researches, 0);
         }
-        return (new_game, moves);
     }
 
-    #[test]
-    fn test_win_in_two() {
+    #[rstest]
+    #[case::late_move_reduction(Some(3), None)]
+    #[case::futility(None, Some(20))]
+    #[case::all(Some(3), Some(20))]
+    fn test_selective_search(
+        win_in_five: Connect4Game,
+        #[case] late_move_reduction: Option<usize>,
+        #[case] futility_margin: Option<Score>,
+        #[values(SearchAlgorithm::Minimax, SearchAlgorithm::NegamaxPvs)] algorithm: SearchAlgorithm,
+    ) {
+        let game = win_in_five;
+        let full_width = plain_search(&game, 9);
+        let mut minimax = Minimax::new(MinimaxParams {
+            max_depth: 9,
+            algorithm,
+            null_move_reduction: Some(2),
+            late_move_reduction,
+            futility_margin,
+            ..Default::default()
+        });
+        let result = minimax.minimax(&game);
+        let stats = minimax.get_selective_stats();
+        // selective search can miss things, but not this win
+        assert_x_wins(&game, &result);
+        assert!(result.nodes_examined < full_width.nodes_examined);
+        // connect 4 has zugzwang, passing is never allowed
+        assert_eq!(stats.null_move_cutoffs, 0);
+        assert_eq!(late_move_reduction.is_some(), stats.reductions > 0);
+        assert_eq!(futility_margin.is_some(), stats.futility_prunes > 0);
+    }
+
+    #[rstest]
+    fn test_extensions(
+        #[values(SearchAlgorithm::Minimax, SearchAlgorithm::NegamaxPvs)] algorithm: SearchAlgorithm,
+    ) {
         let state = "
         .......
         .......
         .......
-        XX.....
-        XX.OX.O
-        OOOXXOO";
-        let game = Connect4Game::from_state(state, None, crate::game::Player::X);
-        let (final_game, moves) = play(game, 7);
+        ....O..
+        ..XOO..
+        X.OOXXX";
+        let game = Connect4Game::from_state(state, Some((3, 4)), Player::X);
+        let search = |max_extension| {
+            let mut minimax = Minimax::new(MinimaxParams {
+                max_depth: 2,
+                algorithm,
+                max_extension,
+                ..Default::default()
+            });
+            let result = minimax.minimax(&game);
+            (result.best_move, minimax.get_selective_stats().extensions)
+        };
+        // the threat to answer is past the depth limit
+        assert_eq!(search(0), (Some(5), 0));
+        let (best_move, extensions) = search(4);
+        assert_eq!(best_move, Some(3));
+        assert!(extensions > 0);
+    }
+
+    #[rstest]
+    #[case::win_in_two(WIN_IN_TWO, 7)]
+    #[case::win_in_five(WIN_IN_FIVE, 9)]
+    fn test_parallel_search(
+        #[case] state: &str,
+        #[case] depth: u32,
+        #[values(1, 4)] threads: usize,
+        #[values(false, true)] cache_enabled: bool,
+    ) {
+        let game = Connect4Game::from_state(state, None, Player::X);
+        let expected = plain_search(&game, depth);
+        let result = Minimax::new(MinimaxParams {
+            max_depth: depth,
+            cache_enabled,
+            threads,
+            ..Default::default()
+        })
+        .minimax_parallel(&game);
+        assert_eq!(result.score, expected.score);
+        assert_x_wins(&game, &result);
+    }
+
+    #[rstest]
+    fn test_parallel_search_keeps_workers(win_in_five: Connect4Game) {
+        let mut minimax = Minimax::new(MinimaxParams {
+            max_depth: 7,
+            cache_enabled: true,
+            threads: 4,
+            ..Default::default()
+        });
+        let first = minimax.minimax_parallel(&win_in_five);
+        let second = minimax.minimax_parallel(&win_in_five);
+        assert_eq!(second.score, first.score);
+        // same threads, so their caches still have the first search
+        assert!(second.nodes_examined < first.nodes_examined);
+    }
+
+    #[rstest]
+    fn test_iterative_deepening_finds_win(
+        #[values(false, true)] cache_enabled: bool,
+        #[values(SearchAlgorithm::Minimax, SearchAlgorithm::Mtdf)] algorithm: SearchAlgorithm,
+    ) {
+        let game = Connect4Game::from_state(WIN_IN_TWO, None, crate::game::Player::X);
+        let mut minimax = Minimax::new(MinimaxParams {
+            max_depth: 7,
+            cache_enabled,
+            algorithm,
+            time_budget: Some(Duration::from_secs(60)),
+            ..Default::default()
+        });
+        let result = minimax.minimax(&game);
+        let final_game = apply_moves(&game, &result.principal_variation)
+            .pop()
+            .unwrap();
         assert_eq!(final_game.get_winner(), Player::X);
-        assert_eq!(moves, 3);
     }
 
     #[test]
-    fn test_win_in_three() {
+    fn test_iterative_deepening_out_of_time() {
+        let game = Connect4Game::default();
+        let mut minimax = Minimax::new(MinimaxParams {
+            max_depth: 42,
+            time_budget: Some(Duration::from_millis(50)),
+            ..Default::default()
+        });
+        let start = Instant::now();
+        let result = minimax.minimax(&game);
+        // generous margin for slow CI machines, a full search would take forever
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert!(result.best_move.is_some());
+    }
+
+    #[test]
+    fn test_progress_listener() {
+        let game = Connect4Game::from_state(WIN_IN_TWO, None, crate::game::Player::X);
+        let mut minimax = Minimax::new(MinimaxParams {
+            max_depth: 4,
+            cache_enabled: true,
+            time_budget: Some(Duration::from_secs(60)),
+            ..Default::default()
+        });
+        let events = Rc::new(RefCell::new(vec![]));
+        let events_listener = events.clone();
+        minimax.add_progress_listener(move |progress| {
+            events_listener.borrow_mut().push(progress.clone())
+        });
+        let result = minimax.minimax(&game);
+
+        let events = events.borrow();
+        let depths: Vec<u32> = events.iter().map(|p| p.depth).collect();
+        assert_eq!(depths, vec![1, 2, 3, 4]);
+        assert!(events.windows(2).all(|w| w[0].nodes < w[1].nodes));
+        let last = events.last().unwrap();
+        assert_eq!(last.best_move, result.best_move);
+        assert_eq!(last.principal_variation, result.principal_variation);
+        assert_eq!(last.score, result.score);
+        assert!(last.cache_hit_rate > 0.);
+    }
+
+    #[test]
+    fn test_max_nodes_limit() {
+        let game = Connect4Game::default();
+        let mut minimax = Minimax::new(MinimaxParams {
+            max_depth: 42,
+            max_nodes: Some(1000),
+            ..Default::default()
+        });
+        let result = minimax.minimax(&game);
+        assert!(result.best_move.is_some());
+        assert!(minimax.was_interrupted());
+        let (_, nodes_examined_last_run, _) = minimax.get_internal_stats();
+        assert_eq!(nodes_examined_last_run, 1000);
+        assert_eq!(result.nodes_examined, 1000);
+        assert!(result.interrupted);
+    }
+
+    #[test]
+    fn test_deadline_limit() {
+        let game = Connect4Game::default();
+        let mut minimax = Minimax::new(MinimaxParams {
+            max_depth: 42,
+            deadline: Some(Instant::now() + Duration::from_millis(50)),
+            ..Default::default()
+        });
+        let result = minimax.minimax(&game);
+        assert!(result.best_move.is_some());
+        assert!(minimax.was_interrupted());
+    }
+
+    #[rstest]
+    fn test_stop_from_other_thread(
+        #[values(None, Some(Duration::from_secs(60)))] time_budget: Option<Duration>,
+    ) {
+        let game = Connect4Game::default();
+        let mut minimax = Minimax::new(MinimaxParams {
+            max_depth: 42,
+            time_budget,
+            ..Default::default()
+        });
+        let stop_handle = minimax.stop_handle();
+        let stopper = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            stop_handle.stop();
+        });
+        let result = minimax.minimax(&game);
+        stopper.join().unwrap();
+        assert!(result.best_move.is_some());
+        assert!(minimax.was_interrupted());
+
+        // the stop was consumed, next search runs normally
+        minimax.minimax(&Connect4Game::from_state(
+            "
+            XOXOXO.
+            OXOXOXO
+            XOXOXOX
+            XOXOXOX
+            OXOXOXO
+            XOXOXOX",
+            None,
+            Player::X,
+        ));
+        assert!(!minimax.was_interrupted());
+    }
+
+    #[test]
+    fn test_threats() {
         let state = "
         .......
         .......
-        ..X....
-        X.O....
-        O.X....
-        XXOOOXO";
-        let game = Connect4Game::from_state(state, None, crate::game::Player::X);
-        let (final_game, moves) = play(game, 7);
-        assert_eq!(final_game.get_winner(), Player::X);
-        // assert_eq!(moves, 5); // TODO pruning does not always select the shortest path
-    }
+        .......
+        .......
+        .......
+        OOO.XX.";
+        let game = Connect4Game::from_state(state, None, Player::X);
+        assert!(game.is_unstable());
+        assert!(game.is_forcing(3));
+        assert!(!game.is_forcing(6));
 
-    #[test]
-    fn test_win_in_four() {
         let state = "
         .......
         .......
         .......
-        .O..OXO
-        OX.XXXO
-        XO.OXOX";
-        let game = Connect4Game::from_state(state, None, crate::game::Player::X);
-        let (final_game, moves) = play(game, 9);
-        assert_eq!(final_game.get_winner(), Player::X);
-        // assert_eq!(moves, 7); // TODO pruning does not always select the shortest path
+        .......
+        .......
+        OO..XX.";
+        let game = Connect4Game::from_state(state, None, Player::X);
+        assert!(!game.is_unstable());
     }
 
     #[test]
-    fn test_win_in_five() {
+    fn test_in_place_matches_copies() {
         let state = "
         .......
         .......
-        .......
-        .....X.
-        .XOOXO.
-        .XXOOXO";
-        let game = Connect4Game::from_state(state, None, crate::game::Player::X);
-        let (final_game, moves) = play(game, 9);
-        assert_eq!(final_game.get_winner(), Player::X);
-        assert_eq!(moves, 9);
+        ...X...
+        ..OO...
+        ..XXO..
+        .XXOO..";
+        let game = Connect4Game::from_state(state, Some((4, 4)), Player::X);
+        let search = |in_place_moves| {
+            let mut minimax = Minimax::new(MinimaxParams {
+                max_depth: 6,
+                in_place_moves,
+                ..Default::default()
+            });
+            let result = minimax.minimax(&game);
+            (result.best_move, result.score, result.nodes_examined)
+        };
+        assert_eq!(search(true), search(false));
     }
 
     #[test]
@@ -531,4 +1080,32 @@ mod tests {
         let score = game.evaluate_score();
         assert_eq!(score.score, 1000);
     }
+
+    #[rstest]
+    // last column
+    #[case(
+        "
+        .......
+        .......
+        ......X
+        ......X
+        O.....X
+        OO....X"
+    )]
+    // diagonal / from the bottom left corner
+    #[case(
+        "
+        .......
+        .......
+        ...X...
+        ..XO...
+        .XOO...
+        XOOX..."
+    )]
+    fn test_score_covers_whole_board(#[case] state: &str) {
+        let game = Connect4Game::from_state(state, None, Player::O);
+        let score = game.evaluate_score();
+        assert!(score.is_terminal);
+        assert_eq!(score.score, 1000);
+    }
 }
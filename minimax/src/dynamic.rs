@@ -0,0 +1,223 @@
+use std::fmt::Debug;
+
+use crate::{game::*, minimax::*};
+
+/// Dyn compatible version of [`MinimaxDriver`], for frontends that pick the game at runtime.
+/// Every game implements it, and boxed games can be searched like any other game,
+/// at the cost of dynamic dispatch on every node.
+/// Methods have a `dyn_` prefix so calls stay unambiguous when both traits are in scope
+pub trait DynMinimaxDriver: Debug {
+    type Move: GameMove;
+    type Hash: HashKey;
+
+    fn dyn_evaluate_score(&self) -> EvaluationScore;
+    fn dyn_get_possible_moves(&self) -> Box<dyn Iterator<Item = Self::Move> + '_>;
+    fn dyn_make_move(&mut self, next_move: Self::Move);
+    fn dyn_unmake_move(&mut self, last_move: Self::Move);
+    fn clone_box(&self) -> BoxedGame<Self::Move, Self::Hash>;
+    fn dyn_get_hash(&self) -> Self::Hash;
+    fn dyn_get_verification_hash(&self) -> Option<Self::Hash>;
+    fn dyn_move_order_hint(&self, next_move: Self::Move) -> Option<Score>;
+    fn dyn_null_move_allowed(&self) -> bool;
+    fn dyn_make_null_move(&mut self);
+    fn dyn_unmake_null_move(&mut self);
+    fn dyn_is_unstable(&self) -> bool;
+    fn dyn_is_forcing(&self, next_move: Self::Move) -> bool;
+    fn dyn_is_chance_node(&self) -> bool;
+    fn dyn_chance_outcomes(&self) -> Vec<(Self::Move, f64)>;
+    fn dyn_get_current_player(&self) -> Player;
+    fn dyn_has_ended(&self) -> bool;
+    fn dyn_get_winner(&self) -> Player;
+}
+
+pub type BoxedGame<M, H = GameHash> = Box<dyn DynMinimaxDriver<Move = M, Hash = H>>;
+
+impl<G: MinimaxDriver + 'static> DynMinimaxDriver for G {
+    type Move = G::Move;
+    type Hash = G::Hash;
+
+    fn dyn_evaluate_score(&self) -> EvaluationScore {
+        MinimaxDriver::evaluate_score(self)
+    }
+
+    fn dyn_get_possible_moves(&self) -> Box<dyn Iterator<Item = Self::Move> + '_> {
+        Box::new(MinimaxDriver::get_possible_moves(self))
+    }
+
+    fn dyn_make_move(&mut self, next_move: Self::Move) {
+        MinimaxDriver::make_move(self, next_move)
+    }
+
+    fn dyn_unmake_move(&mut self, last_move: Self::Move) {
+        MinimaxDriver::unmake_move(self, last_move)
+    }
+
+    fn clone_box(&self) -> BoxedGame<Self::Move, Self::Hash> {
+        Box::new(self.clone())
+    }
+
+    fn dyn_get_hash(&self) -> Self::Hash {
+        MinimaxDriver::get_hash(self)
+    }
+
+    fn dyn_get_verification_hash(&self) -> Option<Self::Hash> {
+        MinimaxDriver::get_verification_hash(self)
+    }
+
+    fn dyn_move_order_hint(&self, next_move: Self::Move) -> Option<Score> {
+        MinimaxDriver::move_order_hint(self, next_move)
+    }
+
+    fn dyn_null_move_allowed(&self) -> bool {
+        MinimaxDriver::null_move_allowed(self)
+    }
+
+    fn dyn_make_null_move(&mut self) {
+        MinimaxDriver::make_null_move(self)
+    }
+
+    fn dyn_unmake_null_move(&mut self) {
+        MinimaxDriver::unmake_null_move(self)
+    }
+
+    fn dyn_is_unstable(&self) -> bool {
+        MinimaxDriver::is_unstable(self)
+    }
+
+    fn dyn_is_forcing(&self, next_move: Self::Move) -> bool {
+        MinimaxDriver::is_forcing(self, next_move)
+    }
+
+    fn dyn_is_chance_node(&self) -> bool {
+        MinimaxDriver::is_chance_node(self)
+    }
+
+    fn dyn_chance_outcomes(&self) -> Vec<(Self::Move, f64)> {
+        MinimaxDriver::chance_outcomes(self)
+    }
+
+    fn dyn_get_current_player(&self) -> Player {
+        MinimaxDriver::get_current_player(self)
+    }
+
+    fn dyn_has_ended(&self) -> bool {
+        MinimaxDriver::has_ended(self)
+    }
+
+    fn dyn_get_winner(&self) -> Player {
+        MinimaxDriver::get_winner(self)
+    }
+}
+
+impl<M: GameMove + 'static, H: HashKey + 'static> Clone for BoxedGame<M, H> {
+    fn clone(&self) -> Self {
+        // going through the box would end up back here
+        self.as_ref().clone_box()
+    }
+}
+
+impl<M: GameMove + 'static, H: HashKey + 'static> MinimaxDriver for BoxedGame<M, H> {
+    type Move = M;
+    type Hash = H;
+
+    fn evaluate_score(&self) -> EvaluationScore {
+        self.as_ref().dyn_evaluate_score()
+    }
+
+    fn get_possible_moves(&self) -> impl Iterator<Item = M> + '_ {
+        self.as_ref().dyn_get_possible_moves()
+    }
+
+    fn make_move(&mut self, next_move: M) {
+        self.as_mut().dyn_make_move(next_move)
+    }
+
+    fn unmake_move(&mut self, last_move: M) {
+        self.as_mut().dyn_unmake_move(last_move)
+    }
+
+    fn get_hash(&self) -> H {
+        self.as_ref().dyn_get_hash()
+    }
+
+    fn get_verification_hash(&self) -> Option<H> {
+        self.as_ref().dyn_get_verification_hash()
+    }
+
+    fn move_order_hint(&self, next_move: M) -> Option<Score> {
+        self.as_ref().dyn_move_order_hint(next_move)
+    }
+
+    fn null_move_allowed(&self) -> bool {
+        self.as_ref().dyn_null_move_allowed()
+    }
+
+    fn make_null_move(&mut self) {
+        self.as_mut().dyn_make_null_move()
+    }
+
+    fn unmake_null_move(&mut self) {
+        self.as_mut().dyn_unmake_null_move()
+    }
+
+    fn is_unstable(&self) -> bool {
+        self.as_ref().dyn_is_unstable()
+    }
+
+    fn is_forcing(&self, next_move: M) -> bool {
+        self.as_ref().dyn_is_forcing(next_move)
+    }
+
+    fn is_chance_node(&self) -> bool {
+        self.as_ref().dyn_is_chance_node()
+    }
+
+    fn chance_outcomes(&self) -> Vec<(M, f64)> {
+        self.as_ref().dyn_chance_outcomes()
+    }
+
+    fn get_current_player(&self) -> Player {
+        self.as_ref().dyn_get_current_player()
+    }
+
+    fn has_ended(&self) -> bool {
+        self.as_ref().dyn_has_ended()
+    }
+
+    fn get_winner(&self) -> Player {
+        self.as_ref().dyn_get_winner()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tictactoe::TicTacToeGame;
+
+    #[test]
+    fn test_boxed_game_search() {
+        let state = "
+        X..
+        .O.
+        ...";
+        let game = TicTacToeGame::from_state(state, Player::X);
+        let boxed: BoxedGame<_> = Box::new(game.clone());
+
+        let static_result = Minimax::new(MinimaxParams::default()).minimax(&game);
+        let dyn_result = Minimax::new(MinimaxParams::default()).minimax(&boxed);
+        assert_eq!(dyn_result.best_move, static_result.best_move);
+        assert_eq!(dyn_result.score, static_result.score);
+        assert_eq!(dyn_result.nodes_examined, static_result.nodes_examined);
+    }
+
+    #[test]
+    fn test_both_traits_in_scope() {
+        let mut game = TicTacToeGame::default();
+        game.make_move((1, 1));
+        assert_eq!(game.get_current_player(), Player::O);
+        let mut boxed: BoxedGame<_> = Box::new(game);
+        boxed.make_move((0, 0));
+        assert_eq!(boxed.get_current_player(), Player::X);
+        assert_eq!(boxed.dyn_get_current_player(), Player::X);
+    }
+}
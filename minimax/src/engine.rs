@@ -0,0 +1,173 @@
+use std::time::Duration;
+
+use rand::{rngs::StdRng, seq::IteratorRandom, SeedableRng};
+
+use crate::{game::*, minimax::*};
+
+/// Anything that can pick a move in a [`MinimaxDriver`] game: [`Minimax`], [`crate::mcts::Mcts`] or the baselines below.
+/// Object safe, so players can be swapped at runtime with `Box<dyn SearchEngine<G>>`
+pub trait SearchEngine<G: MinimaxDriver> {
+    /// Searches the position. Engines that don't search fill in what they can, like the nodes they looked at
+    fn analyze(&mut self, game: &G) -> SearchResult<G::Move>;
+
+    fn choose_move(&mut self, game: &G) -> Option<G::Move> {
+        self.analyze(game).best_move
+    }
+
+    /// Time the next searches can take. Ignored by engines that answer instantly
+    fn set_time_budget(&mut self, _budget: Option<Duration>) {}
+}
+
+/// Plays the game to the end, each engine moving in turn starting with the first one. Gives the winner
+pub fn play_match<G: MinimaxDriver>(game: &G, engines: [&mut dyn SearchEngine<G>; 2]) -> Player {
+    let mut game = game.clone();
+    let mut turn = 0;
+    while !game.has_ended() {
+        let Some(next_move) = engines[turn % 2].choose_move(&game) else {
+            break;
+        };
+        game.make_move(next_move);
+        turn += 1;
+    }
+    game.get_winner()
+}
+
+/// Plays any legal move. Useful as the weakest opponent in tests and tournaments
+pub struct RandomAgent {
+    rng: StdRng,
+}
+
+impl RandomAgent {
+    /// With a seed the agent picks the same moves every game, otherwise it's seeded from the OS
+    pub fn new(seed: Option<u64>) -> Self {
+        Self {
+            rng: match seed {
+                Some(seed) => StdRng::seed_from_u64(seed),
+                None => StdRng::from_entropy(),
+            },
+        }
+    }
+}
+
+impl<G: MinimaxDriver> SearchEngine<G> for RandomAgent {
+    fn analyze(&mut self, game: &G) -> SearchResult<G::Move> {
+        let best_move = game.get_possible_moves().choose(&mut self.rng);
+        SearchResult {
+            best_move,
+            score: 0,
+            principal_variation: best_move.into_iter().collect(),
+            depth: 0,
+            nodes_examined: 0,
+            interrupted: false,
+            tree: None,
+        }
+    }
+}
+
+/// Plays the move with the best evaluation right after it, without looking at the answers.
+/// Takes the wins on the spot but walks into every trap
+#[derive(Default)]
+pub struct GreedyAgent;
+
+impl<G: MinimaxDriver> SearchEngine<G> for GreedyAgent {
+    fn analyze(&mut self, game: &G) -> SearchResult<G::Move> {
+        let multiplier = game.get_current_player().score_multiplier();
+        let mut nodes_examined = 0;
+        let mut best: Option<(G::Move, Score)> = None;
+        for next_move in game.get_possible_moves() {
+            let score = game.apply_move(next_move).evaluate_score().score;
+            nodes_examined += 1;
+            // strictly better only, equal moves keep the order the game gives them in
+            if best.is_none_or(|(_, best)| score * multiplier > best * multiplier) {
+                best = Some((next_move, score));
+            }
+        }
+        SearchResult {
+            best_move: best.map(|(best_move, _)| best_move),
+            score: best.map_or(0, |(_, score)| score),
+            principal_variation: best.map(|(best_move, _)| best_move).into_iter().collect(),
+            depth: 1,
+            nodes_examined,
+            interrupted: false,
+            tree: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{connect4::Connect4Game, mcts::*, tictactoe::TicTacToeGame};
+    use rstest::*;
+
+    fn engines() -> Vec<Box<dyn SearchEngine<TicTacToeGame>>> {
+        vec![
+            Box::new(Minimax::new(MinimaxParams::default())),
+            Box::new(Mcts::new(MctsParams {
+                seed: Some(0),
+                ..Default::default()
+            })),
+            Box::new(GreedyAgent),
+        ]
+    }
+
+    #[test]
+    fn test_engines_take_the_win() {
+        let game = TicTacToeGame::from_state(
+            "
+            XX.
+            OO.
+            ...",
+            Player::X,
+        );
+        for mut engine in engines() {
+            assert_eq!(engine.choose_move(&game), Some((0, 2)));
+        }
+    }
+
+    #[rstest]
+    fn test_random_agent_plays_legal_moves(#[values(0, 1, 2, 3)] seed: u64) {
+        let game = Connect4Game::default();
+        let mut agent = RandomAgent::new(Some(seed));
+        let result = SearchEngine::<Connect4Game>::analyze(&mut agent, &game);
+        let legal: Vec<_> = game.get_possible_moves().collect();
+        assert!(legal.contains(&result.best_move.unwrap()));
+        // same seed, same move
+        let mut again = RandomAgent::new(Some(seed));
+        assert_eq!(again.choose_move(&game), result.best_move);
+    }
+
+    #[test]
+    fn test_greedy_agent() {
+        let state = "
+        .......
+        .......
+        .......
+        ...O...
+        ..OX...
+        .OXX.X.";
+        let game = Connect4Game::from_state(state, Some((3, 3)), Player::X);
+        let result = GreedyAgent.analyze(&game);
+        assert_eq!(result.best_move, Some(4));
+        assert_eq!(result.nodes_examined, 7);
+        assert_eq!(result.principal_variation, vec![4]);
+    }
+
+    #[rstest]
+    fn test_minimax_never_loses_to_random(#[values(0, 1, 2, 3, 4)] seed: u64) {
+        let game = TicTacToeGame::default();
+        let mut minimax = Minimax::new(MinimaxParams::default());
+        let mut random = RandomAgent::new(Some(seed));
+        assert_ne!(play_match(&game, [&mut random, &mut minimax]), Player::X);
+        assert_ne!(play_match(&game, [&mut minimax, &mut random]), Player::O);
+    }
+
+    #[test]
+    fn test_time_budget() {
+        let game = Connect4Game::default();
+        let mut engine: Box<dyn SearchEngine<Connect4Game>> =
+            Box::new(Minimax::new(MinimaxParams::default()));
+        engine.set_time_budget(Some(Duration::from_millis(50)));
+        assert!(engine.choose_move(&game).is_some());
+    }
+}
@@ -24,7 +24,6 @@ impl From<char> for Player {
             '.' => Player::None,
             _ => panic!(),
         }
-        .into()
     }
 }
 
@@ -37,5 +36,3 @@ impl Player {
         }
     }
 }
-
-pub type Move = (usize, usize); // TODO revisit types with performance benchmarks
@@ -1,4 +1,12 @@
 pub mod connect4;
+pub mod dynamic;
+pub mod engine;
 pub mod game;
+pub mod mcts;
 pub mod minimax;
+pub mod multiplayer;
+pub mod proof_number;
+pub mod three_player_connect;
 pub mod tictactoe;
+pub mod transposition;
+pub mod zobrist;
@@ -0,0 +1,333 @@
+use std::time::{Duration, Instant};
+
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+
+use crate::{engine::SearchEngine, game::*, minimax::*};
+
+/// Win rates are turned into scores in this range, so results compare with heuristic scores
+const SCORE_SCALE: f64 = 1000.;
+
+pub struct MctsParams {
+    /// Higher explores more moves, lower goes deeper on the best ones. sqrt(2) in theory
+    pub exploration: f64,
+    /// Playouts to run. The search stops at whichever limit comes first
+    pub max_iterations: Option<u64>,
+    pub time_budget: Option<Duration>,
+    /// Playouts longer than this count as draws
+    pub max_playout_depth: u32,
+    /// Drives the expansion order and the playouts, a fixed seed replays the same search.
+    /// Seeded from the OS if not set
+    pub seed: Option<u64>,
+}
+
+impl Default for MctsParams {
+    fn default() -> Self {
+        Self {
+            exploration: std::f64::consts::SQRT_2,
+            max_iterations: Some(10_000),
+            time_budget: None,
+            max_playout_depth: 200,
+            seed: None,
+        }
+    }
+}
+
+struct MctsNode<M> {
+    /// None for the root
+    last_move: Option<M>,
+    /// Player that made `last_move`. Rewards are from the point of view of `player`
+    player: Player,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    untried_moves: Vec<M>,
+    visits: u32,
+    /// Sum of the playout results
+    reward: f64,
+}
+
+/// Monte Carlo tree search with UCT, see https://en.wikipedia.org/wiki/Monte_Carlo_tree_search.
+/// Only needs the rules of the game, random playouts replace the evaluation
+pub struct Mcts<G: MinimaxDriver> {
+    params: MctsParams,
+    rng: StdRng,
+    // tree in the order it grew, linked by indexes into the vec. nodes[0] is the root
+    nodes: Vec<MctsNode<G::Move>>,
+}
+
+impl<G: MinimaxDriver<Move = M>, M: GameMove> Mcts<G> {
+    pub fn new(params: MctsParams) -> Self {
+        Self {
+            rng: match params.seed {
+                Some(seed) => StdRng::seed_from_u64(seed),
+                None => StdRng::from_entropy(),
+            },
+            params,
+            nodes: vec![],
+        }
+    }
+
+    pub fn search(&mut self, game: &G) -> SearchResult<M> {
+        let start = Instant::now();
+        self.nodes.clear();
+        self.add_node(game, None, Player::None, None);
+        let mut iterations = 0;
+        while !self.out_of_budget(iterations, start) {
+            self.iterate(game);
+            iterations += 1;
+        }
+
+        // the most visited moves are the most trusted ones
+        let mut principal_variation = vec![];
+        let mut node = 0;
+        while let Some(&child) = self.nodes[node]
+            .children
+            .iter()
+            .max_by_key(|&&child| self.nodes[child].visits)
+        {
+            principal_variation.extend(self.nodes[child].last_move);
+            node = child;
+        }
+        SearchResult {
+            best_move: principal_variation.first().copied(),
+            score: self.root_score(),
+            depth: principal_variation.len() as u32,
+            principal_variation,
+            nodes_examined: iterations as u128,
+            interrupted: false,
+            tree: None,
+        }
+    }
+
+    fn out_of_budget(&self, iterations: u64, start: Instant) -> bool {
+        matches!(self.params.max_iterations, Some(max) if iterations >= max)
+            || matches!(self.params.time_budget, Some(budget) if start.elapsed() >= budget)
+            // without limits run a single iteration instead of forever
+            || (self.params.max_iterations.is_none() && self.params.time_budget.is_none() && iterations > 0)
+    }
+
+    /// Selection, expansion, playout and backpropagation
+    fn iterate(&mut self, root: &G) {
+        let mut game = root.clone();
+        let mut node = 0;
+        // go down the tree while all the moves have been tried
+        while self.nodes[node].untried_moves.is_empty() && !self.nodes[node].children.is_empty() {
+            node = self.select_child(node);
+            game.make_move(self.nodes[node].last_move.unwrap());
+        }
+        // try a new move
+        if !self.nodes[node].untried_moves.is_empty() {
+            let idx = self.rng.gen_range(0..self.nodes[node].untried_moves.len());
+            let next_move = self.nodes[node].untried_moves.swap_remove(idx);
+            let player = game.get_current_player();
+            game.make_move(next_move);
+            node = self.add_node(&game, Some(next_move), player, Some(node));
+        }
+        let winner = self.playout(&mut game);
+
+        let mut current = Some(node);
+        while let Some(idx) = current {
+            let node = &mut self.nodes[idx];
+            node.visits += 1;
+            node.reward += match winner {
+                Player::None => 0.5,
+                winner if winner == node.player => 1.,
+                _ => 0.,
+            };
+            current = node.parent;
+        }
+    }
+
+    fn select_child(&self, node: usize) -> usize {
+        let parent_visits = (self.nodes[node].visits as f64).ln();
+        let uct = |child: usize| {
+            let child = &self.nodes[child];
+            let visits = child.visits as f64;
+            child.reward / visits + self.params.exploration * (parent_visits / visits).sqrt()
+        };
+        *self.nodes[node]
+            .children
+            .iter()
+            .max_by(|a, b| uct(**a).total_cmp(&uct(**b)))
+            .unwrap()
+    }
+
+    /// Plays random moves until the game ends. Gives the winner
+    fn playout(&mut self, game: &mut G) -> Player {
+        for _ in 0..self.params.max_playout_depth {
+            if let Some(winner) = winner(game) {
+                return winner;
+            }
+            let moves: Vec<M> = game.get_possible_moves().collect();
+            match moves.choose(&mut self.rng) {
+                Some(next_move) => game.make_move(*next_move),
+                None => return Player::None,
+            }
+        }
+        winner(game).unwrap_or(Player::None)
+    }
+
+    fn add_node(
+        &mut self,
+        game: &G,
+        last_move: Option<M>,
+        player: Player,
+        parent: Option<usize>,
+    ) -> usize {
+        let untried_moves = match winner(game) {
+            Some(_) => vec![],
+            None => game.get_possible_moves().collect(),
+        };
+        self.nodes.push(MctsNode {
+            last_move,
+            player,
+            parent,
+            children: vec![],
+            untried_moves,
+            visits: 0,
+            reward: 0.,
+        });
+        let idx = self.nodes.len() - 1;
+        if let Some(parent) = parent {
+            self.nodes[parent].children.push(idx);
+        }
+        idx
+    }
+
+    /// Win rate of the player to move in the root, as a score from X's point of view
+    fn root_score(&self) -> Score {
+        let root = &self.nodes[0];
+        let best = root
+            .children
+            .iter()
+            .map(|&child| &self.nodes[child])
+            .max_by_key(|child| child.visits);
+        let Some(best) = best.filter(|best| best.visits > 0) else {
+            return 0;
+        };
+        let win_rate = best.reward / best.visits as f64;
+        let score = ((win_rate * 2. - 1.) * SCORE_SCALE) as Score;
+        match best.player {
+            Player::O => -score,
+            _ => score,
+        }
+    }
+}
+
+impl<G: MinimaxDriver<Move = M>, M: GameMove> SearchEngine<G> for Mcts<G> {
+    fn analyze(&mut self, game: &G) -> SearchResult<M> {
+        self.search(game)
+    }
+
+    /// The iteration limit still applies, whichever comes first
+    fn set_time_budget(&mut self, budget: Option<Duration>) {
+        self.params.time_budget = budget;
+    }
+}
+
+/// Winner if the game is over, [`Player::None`] for a draw
+fn winner<G: MinimaxDriver>(game: &G) -> Option<Player> {
+    let evaluation = game.evaluate_score();
+    if evaluation.is_terminal {
+        return Some(match evaluation.score.signum() {
+            1 => Player::X,
+            -1 => Player::O,
+            _ => Player::None,
+        });
+    }
+    game.get_possible_moves()
+        .next()
+        .is_none()
+        .then_some(Player::None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{connect4::Connect4Game, tictactoe::TicTacToeGame};
+    use rstest::*;
+
+    fn seeded(seed: u64) -> MctsParams {
+        MctsParams {
+            max_iterations: Some(2000),
+            seed: Some(seed),
+            ..Default::default()
+        }
+    }
+
+    #[rstest]
+    // win on the spot
+    #[case(
+        "
+        XX.
+        OO.
+        ...",
+        Player::X,
+        (0, 2)
+    )]
+    // block the opponent
+    #[case(
+        "
+        X..
+        OO.
+        X..",
+        Player::X,
+        (1, 2)
+    )]
+    #[case(
+        "
+        XX.
+        ...
+        O..",
+        Player::O,
+        (0, 2)
+    )]
+    fn test_tictactoe(
+        #[case] board_str: &str,
+        #[case] player: Player,
+        #[case] expected: (usize, usize),
+        #[values(1, 2, 3)] seed: u64,
+    ) {
+        let game = TicTacToeGame::from_state(board_str, player);
+        let result = Mcts::new(seeded(seed)).search(&game);
+        assert_eq!(result.best_move, Some(expected));
+        assert_eq!(result.nodes_examined, 2000);
+    }
+
+    #[test]
+    fn test_connect4_win_in_one() {
+        let state = "
+        .......
+        .......
+        .......
+        ...O...
+        ..OX...
+        .OXX.X.";
+        let game = Connect4Game::from_state(state, Some((3, 3)), Player::X);
+        let result = Mcts::new(seeded(0)).search(&game);
+        assert_eq!(result.best_move, Some(4));
+        assert!(result.score > 0);
+        assert_eq!(result.principal_variation[0], 4);
+    }
+
+    #[test]
+    fn test_same_seed_same_search() {
+        let game = TicTacToeGame::default();
+        let first = Mcts::new(seeded(42)).search(&game);
+        let second = Mcts::new(seeded(42)).search(&game);
+        assert_eq!(first.best_move, second.best_move);
+        assert_eq!(first.principal_variation, second.principal_variation);
+        assert_eq!(first.score, second.score);
+    }
+
+    #[test]
+    fn test_time_budget() {
+        let params = MctsParams {
+            max_iterations: None,
+            time_budget: Some(Duration::from_millis(50)),
+            ..Default::default()
+        };
+        let result = Mcts::new(params).search(&TicTacToeGame::default());
+        assert!(result.best_move.is_some());
+        assert!(result.nodes_examined > 0);
+    }
+}
@@ -1,37 +1,172 @@
-use std::{collections::HashMap, rc::Rc};
+use std::{
+    cmp::Reverse,
+    collections::HashMap,
+    fmt::Debug,
+    hash::Hash,
+    ops::ControlFlow,
+    rc::Rc,
+    sync::{
+        atomic::{AtomicBool, AtomicI32, AtomicUsize, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
 
 use itertools::Itertools;
 use tracing::*;
 
-use crate::game::*;
+use crate::{engine::SearchEngine, game::*, transposition::*};
 
 pub type GameHash = u128; // this won't be enough for chess for example
 pub type Score = i32;
-pub type NodeType = Rc<DecisionTreeNode>;
+pub type NodeType<M> = Rc<DecisionTreeNode<M>>;
 
+#[derive(Clone, Copy, Debug)]
 pub struct EvaluationScore {
+    /// Positive is good for X. For terminal states only the sign matters
     pub score: Score,
     pub is_terminal: bool,
 }
 
-pub trait MinimaxDriver: core::fmt::Debug {
+/// Score of a win on the spot. Heuristic scores need to stay well below [`WIN_THRESHOLD`]
+pub const WIN_SCORE: Score = 1_000_000;
+/// Scores above this (or below the negation) are wins
+pub const WIN_THRESHOLD: Score = WIN_SCORE - 10_000;
+
+/// Score of a forced win for `winner`, `plies` moves away from the scored node
+pub fn win_score(winner: Player, plies: u32) -> Score {
+    winner.score_multiplier() * (WIN_SCORE - plies as Score)
+}
+
+/// Moves a score one ply up the tree: wins get one ply further away, the rest stays
+fn score_to_parent(score: Score) -> Score {
+    if score > WIN_THRESHOLD {
+        score - 1
+    } else if score < -WIN_THRESHOLD {
+        score + 1
+    } else {
+        score
+    }
+}
+
+/// Inverse of [`score_to_parent`], used to move the search window down the tree
+fn score_to_child(score: Score) -> Score {
+    if score > WIN_THRESHOLD {
+        score.saturating_add(1)
+    } else if score < -WIN_THRESHOLD {
+        score.saturating_sub(1)
+    } else {
+        score
+    }
+}
+
+/// What a score means from the point of view of one player
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum ScoreOutcome {
+    /// Forced win in the given number of plies
+    Win(u32),
+    /// Forced loss in the given number of plies
+    Loss(u32),
+    /// Can't be told apart from a heuristic that evaluates to exactly 0
+    Draw,
+    Heuristic(Score),
+}
+
+impl ScoreOutcome {
+    pub fn from_score(score: Score, player: Player) -> Self {
+        let score = score * player.score_multiplier();
+        if score > WIN_THRESHOLD {
+            ScoreOutcome::Win((WIN_SCORE - score) as u32)
+        } else if score < -WIN_THRESHOLD {
+            ScoreOutcome::Loss((WIN_SCORE + score) as u32)
+        } else if score == 0 {
+            ScoreOutcome::Draw
+        } else {
+            ScoreOutcome::Heuristic(score)
+        }
+    }
+}
+
+/// Bounds needed by the search on a game specific move type
+pub trait GameMove: Copy + Eq + Hash + Debug {}
+
+impl<T: Copy + Eq + Hash + Debug> GameMove for T {}
+
+/// Bounds needed by the transposition table on a game specific hash type
+pub trait HashKey: Copy + Eq + Hash + Debug {}
+
+impl<T: Copy + Eq + Hash + Debug> HashKey for T {}
+
+/// Statically dispatched so the search gets compiled for each game.
+/// See [`crate::dynamic::DynMinimaxDriver`] when the game is only known at runtime
+pub trait MinimaxDriver: Clone + Debug {
+    /// Moves are game specific: grid coordinates, column indexes, jumps, passes etc.
+    type Move: GameMove;
+    /// Usually [`GameHash`]. Can be wider for big games, see [`crate::zobrist::ZobristKey`]
+    type Hash: HashKey;
+
     fn evaluate_score(&self) -> EvaluationScore;
-    // TODO maybe iterator is not necessary here, or can be simplified with impl? need to understand difference between impl and dyn in this case
-    fn get_possible_moves(&self) -> Box<dyn Iterator<Item = Move> + '_>; // TODO should move into evaluation to avoid doing it twice
-    // TODO replace return type with impl MinimaxDriver?
-    fn apply_move(&self, next_move: Move) -> Box<dyn MinimaxDriver>; // TODO move types should be specific for each game. Can probably use generics here
-    fn get_hash(&self) -> GameHash; // TODO can't implement Hash because it is not object safe
+    fn get_possible_moves(&self) -> impl Iterator<Item = Self::Move> + '_; // TODO should move into evaluation to avoid doing it twice
+    /// Copy of the game with the move played
+    fn apply_move(&self, next_move: Self::Move) -> Self {
+        let mut new_game = self.clone();
+        new_game.make_move(next_move);
+        new_game
+    }
+    /// Plays the move in place. Avoids the copy done by [`MinimaxDriver::apply_move`]
+    fn make_move(&mut self, next_move: Self::Move);
+    /// Takes back a move played with [`MinimaxDriver::make_move`]. Moves are taken back in reverse order
+    fn unmake_move(&mut self, last_move: Self::Move);
+    fn get_hash(&self) -> Self::Hash;
+    /// Second hash, independent from [`MinimaxDriver::get_hash`]. Debug builds store it in the cache
+    /// and panic if two different positions end up with the same hash
+    fn get_verification_hash(&self) -> Option<Self::Hash> {
+        None
+    }
+    /// Cheap guess of how good a move is, higher gets searched first.
+    /// Without hints all the children are evaluated to sort them
+    fn move_order_hint(&self, _next_move: Self::Move) -> Option<Score> {
+        None
+    }
+    /// Passing is not a real move in most games, but seeing what the opponent does with two moves in a row
+    /// is a cheap way to find positions good enough to prune. Only used with [`MinimaxParams::null_move_reduction`]
+    fn null_move_allowed(&self) -> bool {
+        false
+    }
+    /// Gives the turn to the other player without playing. Only called if [`MinimaxDriver::null_move_allowed`]
+    fn make_null_move(&mut self) {}
+    fn unmake_null_move(&mut self) {}
+    /// Positions in the middle of a fight, like a threat that has to be answered right away.
+    /// Lines through them get searched past the depth limit, see [`MinimaxParams::max_extension`]
+    fn is_unstable(&self) -> bool {
+        false
+    }
+    /// Moves the opponent has to answer, searched past the depth limit like unstable positions
+    fn is_forcing(&self, _next_move: Self::Move) -> bool {
+        false
+    }
+    /// Positions where chance plays the next move instead of a player, like a dice roll or a random tile spawn.
+    /// The search takes the expected score of [`MinimaxDriver::chance_outcomes`], with any algorithm
+    /// except [`SearchAlgorithm::Mtdf`] when the search starts at a chance node
+    fn is_chance_node(&self) -> bool {
+        false
+    }
+    /// Moves chance can play in a chance node, with their probabilities. Probabilities should add up to 1
+    fn chance_outcomes(&self) -> Vec<(Self::Move, f64)> {
+        vec![]
+    }
     fn get_current_player(&self) -> Player; // TODO only needed to know if maximizing player or minimizing player. maybe better to abstract this somehow?
 
     fn has_ended(&self) -> bool; // TODO only used in clients, should be implemented on game separately
     fn get_winner(&self) -> Player; // TODO remove from trait, not needed anymore
 }
 
-#[derive(Default)]
-pub struct DecisionTreeNode {
+pub struct DecisionTreeNode<M> {
     pub score: Score,
-    pub moves: HashMap<Move, NodeType>, // TODO can moves be a vector instead?
-    pub best_move: Option<Move>,
+    pub moves: HashMap<M, NodeType<M>>, // TODO can moves be a vector instead?
+    pub best_move: Option<M>,
     // Only for debug
     pub alfa: Score,
     pub beta: Score,
@@ -39,171 +174,1874 @@ pub struct DecisionTreeNode {
     pub visit_order: u128,
 }
 
-impl DecisionTreeNode {
-    pub fn get_best_move(&self) -> Option<Move> {
-        return self.best_move;
+// derive(Default) would require M: Default
+impl<M> Default for DecisionTreeNode<M> {
+    fn default() -> Self {
+        Self {
+            score: Default::default(),
+            moves: Default::default(),
+            best_move: None,
+            alfa: Default::default(),
+            beta: Default::default(),
+            estimate: Default::default(),
+            visit_order: Default::default(),
+        }
+    }
+}
+
+impl<M: GameMove> DecisionTreeNode<M> {
+    pub fn get_best_move(&self) -> Option<M> {
+        self.best_move
+    }
+
+    /// Follows the best moves down the tree.
+    /// Stops early where the tree has been pruned or cut off by the cache
+    pub fn principal_variation(&self) -> Vec<M> {
+        let mut line = vec![];
+        let mut node = self;
+        while let Some(next_move) = node.best_move {
+            line.push(next_move);
+            match node.moves.get(&next_move) {
+                Some(child) => node = child,
+                None => break,
+            }
+        }
+        line
     }
 }
 
+/// Alpha beta variant used by [`Minimax`]
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum SearchAlgorithm {
+    /// Maximizing and minimizing player handled separately
+    Minimax,
+    /// Negamax with zero window searches for all moves after the first one
+    NegamaxPvs,
+    /// Only zero window searches, closing in on the score from a first guess.
+    /// Searches with a transposition table of its own, sized like the cache and emptied before every search.
+    /// Ignores [`MinimaxParams::cache_enabled`], the shared cache isn't used.
+    /// See https://www.chessprogramming.org/MTD(f)
+    Mtdf,
+}
+
+/// Pruning on the chance nodes of expectiminimax, see https://en.wikipedia.org/wiki/Expectiminimax
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum ChancePruning {
+    /// Every outcome is searched with the full window
+    None,
+    /// Stops once the outcomes searched so far decide the expectation,
+    /// counting the rest at the worst and best scores of [`MinimaxParams::score_bounds`]
+    Star1,
+    /// Star1 after probing a single move of every outcome, which gives tighter bounds than the score bounds.
+    /// Only probes when the same player moves after all the outcomes.
+    /// The probed moves get searched again later, so it only pays off with the cache enabled
+    Star2,
+}
+
+#[derive(Clone)]
 pub struct MinimaxParams {
     pub max_depth: u32,
-    pub depth_factor: f32,
+    pub algorithm: SearchAlgorithm,
     pub weight_suboptimal: f32,
     pub cache_enabled: bool,
     pub pruning_enabled: bool,
+    /// Enables iterative deepening: depths 1, 2, 3... up to `max_depth` are searched until the time runs out
+    pub time_budget: Option<Duration>,
+    /// Hard limit on the nodes examined in a single call. The best move found so far is returned
+    pub max_nodes: Option<u128>,
+    /// Hard limit on when the search has to return. The best move found so far is returned
+    pub deadline: Option<Instant>,
+    /// Keep the whole decision tree in the result. Slow and memory hungry, meant for debugging
+    pub build_tree: bool,
+    /// Search with make/unmake on a single copy of the game instead of copying it for every node
+    pub in_place_moves: bool,
+    /// Order moves with [`MinimaxDriver::move_order_hint`] when the game has it, instead of evaluating every child
+    pub move_hints: bool,
+    /// Memory taken by the cache once enabled
+    pub cache_size_mb: usize,
+    pub cache_replacement: ReplacementStrategy,
+    /// Moves that caused a cutoff at the same depth are searched first
+    pub killer_moves: bool,
+    /// Moves that caused cutoffs anywhere in the tree are searched first, weighted by how deep the cutoff was
+    pub history_heuristic: bool,
+    /// The move that refuted the opponent's last move is searched first
+    pub counter_moves: bool,
+    /// Starts with a window this far on both sides of the expected score, and widens it if the score falls outside.
+    /// Doesn't apply to [`SearchAlgorithm::Mtdf`], which only searches with zero windows anyway.
+    /// See https://www.chessprogramming.org/Aspiration_Windows
+    pub aspiration_window: Option<Score>,
+    /// Expected score for the aspiration window. If not set, the score of the last search is used
+    pub score_guess: Option<Score>,
+    /// Passes the turn before trying real moves, on games that allow it, with a search this many plies shallower.
+    /// If the opponent can't punish the pass, the node gets pruned. See https://www.chessprogramming.org/Null_Move_Pruning
+    pub null_move_reduction: Option<u32>,
+    /// Moves from this index on are searched one ply shallower, and again at full depth if they turn out good.
+    /// See https://www.chessprogramming.org/Late_Move_Reductions
+    pub late_move_reduction: Option<usize>,
+    /// Near the leaves, moves evaluated this much per remaining ply below alfa are not searched.
+    /// See https://www.chessprogramming.org/Futility_Pruning
+    pub futility_margin: Option<Score>,
+    /// Extra plies a line can go past the depth limit through forcing moves and unstable positions,
+    /// so the search doesn't stop in the middle of a threat. 0 disables extensions
+    pub max_extension: u32,
+    /// Threads used by [`Minimax::minimax_parallel`]
+    pub threads: usize,
+    pub chance_pruning: ChancePruning,
+    /// Lowest and highest score the game can get. The tighter, the more [`ChancePruning`] prunes
+    pub score_bounds: (Score, Score),
 }
 
 impl Default for MinimaxParams {
     fn default() -> Self {
         Self {
             max_depth: 12,
-            depth_factor: 0.98,
+            algorithm: SearchAlgorithm::Minimax,
             weight_suboptimal: 0.,
             cache_enabled: false,
             pruning_enabled: true,
+            time_budget: None,
+            max_nodes: None,
+            deadline: None,
+            build_tree: false,
+            in_place_moves: true,
+            move_hints: true,
+            cache_size_mb: 16,
+            cache_replacement: ReplacementStrategy::DepthPreferred,
+            killer_moves: false,
+            history_heuristic: false,
+            counter_moves: false,
+            aspiration_window: None,
+            score_guess: None,
+            null_move_reduction: None,
+            late_move_reduction: None,
+            futility_margin: None,
+            max_extension: 0,
+            threads: thread::available_parallelism().map_or(1, |n| n.get()),
+            chance_pruning: ChancePruning::None,
+            score_bounds: (-WIN_SCORE, WIN_SCORE),
         }
     }
 }
 
-pub struct Minimax {
+/// Lets another thread stop a running search.
+/// A stop that arrives while no search is running stops the next one
+#[derive(Clone, Default, Debug)]
+pub struct StopHandle(Arc<AtomicBool>);
+
+impl StopHandle {
+    pub fn stop(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    fn reset(&self) {
+        self.0.store(false, Ordering::Relaxed);
+    }
+}
+
+/// Outcome of [`Minimax::minimax`]
+#[derive(Clone)]
+pub struct SearchResult<M> {
+    pub best_move: Option<M>,
+    pub score: Score,
+    /// Best line of play for both players, starting with `best_move`
+    pub principal_variation: Vec<M>,
+    /// Depth of the search the result comes from
+    pub depth: u32,
+    pub nodes_examined: u128,
+    /// True if a stop or one of the limits cut the search short
+    pub interrupted: bool,
+    /// Only there if [`MinimaxParams::build_tree`] is set
+    pub tree: Option<NodeType<M>>,
+}
+
+/// Move to search with, when already there, the game after it and its evaluation
+type Child<G> = (
+    <G as MinimaxDriver>::Move,
+    Option<G>,
+    Option<EvaluationScore>,
+);
+
+/// Score of a searched node and, when building the tree, the node itself
+struct NodeResult<M> {
+    score: Score,
+    tree: Option<NodeType<M>>,
+}
+
+/// Snapshot of the search, sent to the progress listeners after every completed depth
+#[derive(Clone, Debug)]
+pub struct SearchProgress<M> {
+    pub depth: u32,
+    pub best_move: Option<M>,
+    pub score: Score,
+    /// Nodes examined since the search started
+    pub nodes: u128,
+    pub nodes_per_second: f64,
+    pub elapsed: Duration,
+    pub principal_variation: Vec<M>,
+    /// Share of cache lookups that were usable instead of searching the node
+    pub cache_hit_rate: f64,
+}
+
+impl<M: GameMove> core::fmt::Display for SearchProgress<M> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "depth {} score {} nodes {} nps {:.0} time {}ms hits {:.1}% pv {:?}",
+            self.depth,
+            self.score,
+            self.nodes,
+            self.nodes_per_second,
+            self.elapsed.as_millis(),
+            self.cache_hit_rate * 100.,
+            self.principal_variation
+        )
+    }
+}
+
+pub type ProgressListener<M> = Box<dyn FnMut(&SearchProgress<M>)>;
+
+/// Searches the game to the depth. Same arguments as [`Minimax::search`]
+type SearchFn<G, M> = fn(&mut Minimax<G>, &G, u32, &[M], Option<Score>) -> SearchResult<M>;
+
+/// How good the move ordering was in the last search
+#[derive(Clone, Copy, Default, Debug)]
+pub struct OrderingStats {
+    /// Nodes where a move was good enough to prune the rest
+    pub cutoffs: u128,
+    /// Cutoffs caused by the first move searched
+    pub first_move_cutoffs: u128,
+    /// Zero window searches that found a better move and had to be repeated, only for [`SearchAlgorithm::NegamaxPvs`]
+    pub researches: u128,
+    /// Searches repeated with a wider aspiration window because the score was outside of it
+    pub aspiration_researches: u128,
+}
+
+/// How often the selective search techniques kicked in during the last search
+#[derive(Clone, Copy, Default, Debug)]
+pub struct SelectiveStats {
+    /// Nodes pruned because passing the turn was already good enough
+    pub null_move_cutoffs: u128,
+    /// Late moves searched with less depth
+    pub reductions: u128,
+    /// Reduced moves that turned out good and had to be searched again at full depth
+    pub reduction_researches: u128,
+    /// Moves near the leaves not searched because they were too far behind
+    pub futility_prunes: u128,
+    /// Forcing moves and unstable positions searched one ply deeper
+    pub extensions: u128,
+    /// Chance nodes that stopped before searching all their outcomes
+    pub chance_cutoffs: u128,
+    /// Outcomes probed with a single move, only with [`ChancePruning::Star2`]
+    pub chance_probes: u128,
+}
+
+impl OrderingStats {
+    /// Share of the cutoffs found on the first move. The closer to 1 the better the ordering
+    pub fn cutoff_rate(&self) -> f64 {
+        if self.cutoffs == 0 {
+            return 0.;
+        }
+        self.first_move_cutoffs as f64 / self.cutoffs as f64
+    }
+}
+
+pub struct Minimax<G: MinimaxDriver> {
     params: MinimaxParams,
-    cache: HashMap<GameHash, NodeType>,
+    cache: TranspositionTable<G::Move, G::Hash>,
+    // used instead of the cache by MTD(f), which can't do without one
+    mtdf_table: Option<TranspositionTable<G::Move, G::Hash>>,
     nodes_examined_total: u128, // very optimistic size, would probably run out of memory before that
     nodes_examined_last_run: u128,
+    nodes_examined_at_start: u128,
+    // depth limit of the current iteration, same as max_depth if not deepening
+    search_depth: u32,
+    // plies skipped by null moves and reductions on the current line
+    reduction: u32,
+    // plies added by extensions on the current line
+    extension: u32,
+    in_null_move: bool,
+    // soft deadline for iterative deepening
+    deadline: Option<Instant>,
+    stop_handle: StopHandle,
+    // set while unwinding a search that has been stopped
+    aborted: bool,
+    interrupted_last_run: bool,
+    progress_listeners: Vec<ProgressListener<G::Move>>,
+    search_start: Instant,
+    cache_lookups: u128,
+    cache_hits: u128,
+    // best line found below each ply, see https://www.chessprogramming.org/Triangular_PV-Table
+    pv_table: Vec<Vec<G::Move>>,
+    principal_variation: Vec<G::Move>,
+    // first guess for the aspiration window of the next search
+    last_score: Option<Score>,
+    // moves from the root to the current node
+    current_line: Vec<G::Move>,
+    // two most recent per ply, see https://www.chessprogramming.org/Killer_Heuristic
+    killers: Vec<[Option<G::Move>; 2]>,
+    history: HashMap<G::Move, u64>,
+    counter_moves: HashMap<G::Move, G::Move>,
+    ordering_stats: OrderingStats,
+    selective_stats: SelectiveStats,
+    // threads of the parallel search, started by the first one
+    workers: Vec<Worker<G>>,
 }
 
-impl Minimax {
+impl<G: MinimaxDriver<Move = M>, M: GameMove> Minimax<G> {
     pub fn new(params: MinimaxParams) -> Self {
         Self {
-            params: params,
-            cache: Default::default(),
+            cache: TranspositionTable::new(params.cache_size_mb, params.cache_replacement),
+            mtdf_table: (params.algorithm == SearchAlgorithm::Mtdf)
+                .then(|| TranspositionTable::new(params.cache_size_mb, params.cache_replacement)),
+            params,
             nodes_examined_last_run: 0,
             nodes_examined_total: 0,
+            nodes_examined_at_start: 0,
+            search_depth: 0,
+            reduction: 0,
+            extension: 0,
+            in_null_move: false,
+            deadline: None,
+            stop_handle: Default::default(),
+            aborted: false,
+            interrupted_last_run: false,
+            progress_listeners: vec![],
+            search_start: Instant::now(),
+            cache_lookups: 0,
+            cache_hits: 0,
+            pv_table: vec![],
+            principal_variation: vec![],
+            last_score: None,
+            current_line: vec![],
+            killers: vec![],
+            history: Default::default(),
+            counter_moves: Default::default(),
+            ordering_stats: Default::default(),
+            selective_stats: Default::default(),
+            workers: vec![],
         }
     }
 
+    /// Best line of play found by the last search, starting with the best move.
+    /// Doesn't need the decision tree
+    pub fn get_principal_variation(&self) -> &[M] {
+        &self.principal_variation
+    }
+
+    /// The listener is called after every completed depth of the search
+    pub fn add_progress_listener(&mut self, listener: impl FnMut(&SearchProgress<M>) + 'static) {
+        self.progress_listeners.push(Box::new(listener));
+    }
+
+    /// Handle that can be sent to other threads to stop the search
+    pub fn stop_handle(&self) -> StopHandle {
+        self.stop_handle.clone()
+    }
+
+    /// True if the last search was cut short by a stop or one of the limits and did not reach `max_depth`
+    pub fn was_interrupted(&self) -> bool {
+        self.interrupted_last_run
+    }
+
     pub fn get_internal_stats(&self) -> (u128, u128, usize) {
         (
             self.nodes_examined_total,
             self.nodes_examined_last_run,
-            self.cache.len(),
+            self.table().len(),
         )
     }
+
+    /// Counters of the cache over all the searches, or of the table of [`SearchAlgorithm::Mtdf`]
+    pub fn get_cache_stats(&self) -> CacheStats {
+        self.table().stats()
+    }
+
+    pub fn get_ordering_stats(&self) -> OrderingStats {
+        self.ordering_stats
+    }
+
+    pub fn get_selective_stats(&self) -> SelectiveStats {
+        self.selective_stats
+    }
 }
 
-impl Minimax {
-    // TODO maybe use generic instead of dynamic dispatch
-    // or maybe can use impl MinimaxDriver?
-    pub fn minimax(&mut self, game: &dyn MinimaxDriver) -> NodeType {
+impl<G: MinimaxDriver<Move = M>, M: GameMove> Minimax<G> {
+    pub fn minimax(&mut self, game: &G) -> SearchResult<M> {
+        self.run(game, Self::search)
+    }
+
+    fn run(&mut self, game: &G, search: SearchFn<G, M>) -> SearchResult<M> {
         // TODO suboptimal breaks the pruning if too high, and way slower
-        // disabling depth factor is also slightly faster
-        let previous_total = self.nodes_examined_total;
-        let res = self._minimax(game, 0, Score::MIN, Score::MAX, game.evaluate_score());
-        self.nodes_examined_last_run = self.nodes_examined_total - previous_total;
+        self.start_search();
+        let mut res = match self.params.time_budget {
+            Some(budget) => self.iterative_deepening(game, budget, search),
+            None => search(self, game, self.params.max_depth, &[], None),
+        };
+        res.nodes_examined = self.nodes_examined_total - self.nodes_examined_at_start;
+        self.nodes_examined_last_run = res.nodes_examined;
+        self.interrupted_last_run = res.interrupted;
+        self.principal_variation = res.principal_variation.clone();
+        if res.interrupted {
+            self.stop_handle.reset();
+        } else {
+            self.last_score = Some(res.score);
+        }
         res
     }
 
-    fn _minimax(
+    /// Resets what only lasts for one call of [`Minimax::minimax`]
+    fn start_search(&mut self) {
+        self.nodes_examined_at_start = self.nodes_examined_total;
+        self.search_start = Instant::now();
+        self.cache_lookups = 0;
+        self.cache_hits = 0;
+        self.cache.new_search();
+        if let Some(table) = &mut self.mtdf_table {
+            table.clear();
+        }
+        self.ordering_stats = Default::default();
+        self.selective_stats = Default::default();
+        self.killers.clear();
+        self.history.clear();
+        self.counter_moves.clear();
+    }
+
+    /// Returns the result of the deepest iteration that finished in time.
+    /// The time budget only applies after the first iteration so there is always a move,
+    /// but the hard limits can also stop the first iteration
+    fn iterative_deepening(
+        &mut self,
+        game: &G,
+        budget: Duration,
+        search: SearchFn<G, M>,
+    ) -> SearchResult<M> {
+        let start = Instant::now();
+        let mut best = search(self, game, 1, &[], None);
+        self.deadline = Some(start + budget);
+        for depth in 2..=self.params.max_depth {
+            if best.interrupted {
+                break;
+            }
+            // searching the previous best line first gives the most cutoffs
+            let previous_line = best.principal_variation.clone();
+            let res = search(self, game, depth, &previous_line, Some(best.score));
+            if res.interrupted {
+                debug!("Iterative deepening stopped at depth {}", depth);
+                best.interrupted = true;
+                break;
+            }
+            best = res;
+        }
+        self.deadline = None;
+        best
+    }
+
+    /// `guess` is where MTD(f) and aspiration windows start looking for the score, usually the score of the previous depth
+    fn search(
         &mut self,
-        game: &dyn MinimaxDriver,
+        game: &G,
+        depth: u32,
+        pv_hint: &[M],
+        guess: Option<Score>,
+    ) -> SearchResult<M> {
+        let mut root_game = game.clone();
+        if self.params.algorithm == SearchAlgorithm::Mtdf && guess.is_none() && depth > 1 {
+            // a shallow search is cheap and fills the cache for the real one
+            self.search_depth = depth / 2;
+            let root = self._negamax(
+                &mut root_game,
+                0,
+                -Score::MAX,
+                Score::MAX,
+                game.evaluate_score(),
+                pv_hint,
+            );
+            let color = game.get_current_player().score_multiplier();
+            return self.search(game, depth, pv_hint, Some(root.score * color));
+        }
+        self.search_depth = depth;
+        let aspiration = self
+            .params
+            .aspiration_window
+            .zip(guess.or(self.params.score_guess).or(self.last_score));
+        let root = match self.params.algorithm {
+            SearchAlgorithm::Minimax | SearchAlgorithm::NegamaxPvs => match aspiration {
+                Some((window, guess)) => {
+                    self.aspiration_search(&mut root_game, guess, window, pv_hint)
+                }
+                None => self.search_window(
+                    &mut root_game,
+                    0,
+                    Score::MIN,
+                    Score::MAX,
+                    game.evaluate_score(),
+                    pv_hint,
+                ),
+            },
+            SearchAlgorithm::Mtdf => {
+                let color = game.get_current_player().score_multiplier();
+                let guess = guess.unwrap_or(game.evaluate_score().score) * color;
+                let root = self.mtdf(&mut root_game, guess, pv_hint);
+                NodeResult {
+                    score: root.score * color,
+                    tree: root.tree,
+                }
+            }
+        };
+        let mut principal_variation = self.pv_table[0].clone();
+        self.extend_line_from_cache(game, &mut principal_variation);
+        let res = SearchResult {
+            best_move: principal_variation.first().copied(),
+            score: root.score,
+            principal_variation,
+            depth,
+            nodes_examined: self.nodes_examined_total - self.nodes_examined_at_start,
+            interrupted: self.aborted,
+            tree: root.tree,
+        };
+        self.aborted = false;
+        if !res.interrupted {
+            self.report_progress(&res);
+        }
+        res
+    }
+
+    fn report_progress(&mut self, res: &SearchResult<M>) {
+        if self.progress_listeners.is_empty() {
+            return;
+        }
+        let elapsed = self.search_start.elapsed();
+        let progress = SearchProgress {
+            depth: res.depth,
+            best_move: res.best_move,
+            score: res.score,
+            nodes: res.nodes_examined,
+            nodes_per_second: res.nodes_examined as f64 / elapsed.as_secs_f64().max(f64::EPSILON),
+            elapsed,
+            principal_variation: res.principal_variation.clone(),
+            cache_hit_rate: if self.cache_lookups > 0 {
+                self.cache_hits as f64 / self.cache_lookups as f64
+            } else {
+                0.
+            },
+        };
+        for listener in self.progress_listeners.iter_mut() {
+            listener(&progress);
+        }
+    }
+
+    /// Cache hits cut the line short, the rest of it can be found by following the cached best moves
+    fn extend_line_from_cache(&self, game: &G, line: &mut Vec<M>) {
+        if !self.uses_cache() || line.is_empty() {
+            return;
+        }
+        let mut states = apply_moves(game, line);
+        while line.len() < self.search_depth as usize {
+            let current = states.last().unwrap();
+            let next_move = match self.table().peek(current.get_hash()) {
+                Some(entry) if entry.bound == Bound::Exact => entry.best_move,
+                _ => None,
+            };
+            match next_move {
+                Some(next_move) => {
+                    states.push(current.apply_move(next_move));
+                    line.push(next_move);
+                }
+                None => break,
+            }
+        }
+    }
+
+    fn should_stop(&self) -> bool {
+        let nodes_examined = self.nodes_examined_total - self.nodes_examined_at_start;
+        self.stop_handle.is_stopped()
+            || matches!(self.params.max_nodes, Some(max_nodes) if nodes_examined >= max_nodes)
+            || matches!(self.params.deadline, Some(deadline) if Instant::now() >= deadline)
+            || matches!(self.deadline, Some(deadline) if Instant::now() >= deadline)
+    }
+
+    /// Checks that can end a node before looking at its children: stops, the cache and leaves.
+    /// Otherwise gives the move from the cache and the visit order of the node.
+    /// Scores and window are from X's point of view
+    fn enter_node(
+        &mut self,
+        game: &G,
         current_depth: u32,
-        mut alfa: Score, // best for maximizing player
-        mut beta: Score, // best for minimizing player
-        score_eval: EvaluationScore,
-    ) -> NodeType {
+        alfa: Score,
+        beta: Score,
+        score_eval: &EvaluationScore,
+    ) -> ControlFlow<NodeResult<M>, (Option<M>, u128)> {
+        // the root is always expanded so there is a move to fall back on
+        if self.aborted || (current_depth > 0 && self.should_stop()) {
+            // result will be thrown away, stop as fast as possible
+            self.aborted = true;
+            return ControlFlow::Break(NodeResult {
+                score: 0,
+                tree: None,
+            });
+        }
         let current_node_idx = self.nodes_examined_total;
         self.nodes_examined_total += 1;
+        let ply = current_depth as usize;
+        if self.pv_table.len() <= ply + 1 {
+            self.pv_table.resize_with(ply + 2, Vec::new);
+        }
+        self.pv_table[ply].clear();
 
-        let cache_key = game.get_hash();
-        // TODO caching breaks with pruning, not sure how to solve this. see test_doesnt_make_noob_mistake
-        // either make caching optional, as it seems to work better than pruning for tictactoe
-        // or remove completely
-        if self.params.cache_enabled && self.cache.contains_key(&cache_key) {
-            return self.cache.get(&cache_key).unwrap().clone();
+        // entries are only valid if they searched at least as deep as we still need to
+        let remaining_depth = self.remaining_depth(current_depth);
+        let mut hash_move = None;
+        if self.uses_cache() {
+            self.cache_lookups += 1;
+            let cache_key = game.get_hash();
+            // same as table_mut(), borrowing only the tables
+            let table = self.mtdf_table.as_mut().unwrap_or(&mut self.cache);
+            if let Some(entry) = table.get(cache_key) {
+                debug_assert!(
+                    entry.verification.is_none()
+                        || entry.verification == game.get_verification_hash(),
+                    "Hash collision on {:?}",
+                    cache_key
+                );
+                if entry.is_usable(remaining_depth, alfa, beta) {
+                    self.cache_hits += 1;
+                    self.pv_table[ply].extend(entry.best_move);
+                    return ControlFlow::Break(NodeResult {
+                        score: entry.score,
+                        tree: entry.node.clone(),
+                    });
+                }
+                // not good enough for a cutoff, but the best move is still a good first guess
+                hash_move = entry.best_move;
+            }
         }
 
-        if score_eval.is_terminal || current_depth >= self.params.max_depth {
-            let node = Rc::new(DecisionTreeNode {
-                score: score_eval.score,
-                estimate: score_eval.score,
-                visit_order: current_node_idx,
-                // TODO probably should set alfa beta here
-                ..Default::default()
+        if score_eval.is_terminal || remaining_depth == 0 {
+            let score = if score_eval.is_terminal {
+                // TODO a terminal draw is scored as 0, which is fine as long as it's a heuristic of 0 too
+                win_score(Player::X, 0) * score_eval.score.signum()
+            } else {
+                score_eval.score
+            };
+            let tree = self.params.build_tree.then(|| {
+                Rc::new(DecisionTreeNode {
+                    score,
+                    estimate: score_eval.score,
+                    visit_order: current_node_idx,
+                    // TODO probably should set alfa beta here
+                    ..Default::default()
+                })
             });
-            self.cache.insert(cache_key, node.clone());
-            return node;
+            self.cache_node(game, score, None, &tree, remaining_depth, Bound::Exact);
+            return ControlFlow::Break(NodeResult { score, tree });
         }
-        
+        ControlFlow::Continue((hash_move, current_node_idx))
+    }
+
+    /// Children in the order they should be searched
+    fn ordered_children(
+        &mut self,
+        game: &mut G,
+        ply: usize,
+        hash_move: Option<M>,
+        pv_hint: &[M],
+    ) -> Vec<Child<G>> {
         let score_multiplier = game.get_current_player().score_multiplier();
-        let new_states = game
-            .get_possible_moves()
-            .map(|m| {
-                let new_move = game.apply_move(m);
-                let score = new_move.evaluate_score();
-                (m, new_move, score)
-            })
-            .sorted_by_key(|(_, _, score)| -score_multiplier * score.score);
+        let mut possible_moves: Vec<M> = game.get_possible_moves().collect();
+        // with hints from the game the children only get evaluated once they are visited,
+        // so the ones that get pruned cost nothing
+        let lazy = self.params.move_hints
+            && possible_moves
+                .first()
+                .is_some_and(|m| game.move_order_hint(*m).is_some());
+        let mut new_states: Vec<Child<G>> = if lazy {
+            possible_moves.sort_by_key(|m| Reverse(game.move_order_hint(*m).unwrap_or(0)));
+            possible_moves
+                .into_iter()
+                .map(|m| (m, None, None))
+                .collect()
+        } else {
+            // children are only copied if not searching in place
+            possible_moves
+                .into_iter()
+                .map(|m| {
+                    if self.params.in_place_moves {
+                        game.make_move(m);
+                        let score = game.evaluate_score();
+                        game.unmake_move(m);
+                        (m, None, Some(score))
+                    } else {
+                        let new_move = game.apply_move(m);
+                        let score = new_move.evaluate_score();
+                        (m, Some(new_move), Some(score))
+                    }
+                })
+                .sorted_by_key(|(_, _, score)| -score_multiplier * score.as_ref().unwrap().score)
+                .collect()
+        };
+        // the statically best move is still tried first, the heuristics only order the rest
+        let heuristics_start = if lazy { 0 } else { new_states.len().min(1) };
+        if self.params.history_heuristic {
+            // stable sort, the static evaluation still decides between moves without history
+            new_states[heuristics_start..]
+                .sort_by_key(|(m, _, _)| Reverse(self.history.get(m).copied().unwrap_or(0)));
+        }
+        let killers = match self.killers.get(ply) {
+            Some(killers) if self.params.killer_moves => *killers,
+            _ => [None, None],
+        };
+        let counter_move = match self.current_line.last() {
+            Some(last_move) if self.params.counter_moves => {
+                self.counter_moves.get(last_move).copied()
+            }
+            _ => None,
+        };
+        for first_move in [counter_move, killers[1], killers[0]].iter().flatten() {
+            if let Some(idx) = new_states[heuristics_start..]
+                .iter()
+                .position(|(m, _, _)| m == first_move)
+            {
+                new_states[heuristics_start..=heuristics_start + idx].rotate_right(1);
+            }
+        }
+        // the previous iteration's line goes first, then the move from the cache
+        for first_move in hash_move.iter().chain(pv_hint.first()) {
+            if let Some(idx) = new_states.iter().position(|(m, _, _)| m == first_move) {
+                new_states[..=idx].rotate_right(1);
+            }
+        }
+        new_states
+    }
+
+    /// Plays the child's move and runs `search` on the resulting game
+    fn visit_child<T>(
+        &mut self,
+        game: &mut G,
+        (pos, child_game, evaluation): Child<G>,
+        search: impl FnOnce(&mut Self, &mut G, EvaluationScore) -> T,
+    ) -> T {
+        self.current_line.push(pos);
+        let node_eval = if self.params.in_place_moves {
+            game.make_move(pos);
+            let evaluation = evaluation.unwrap_or_else(|| game.evaluate_score());
+            let node_eval = search(self, game, evaluation);
+            game.unmake_move(pos);
+            node_eval
+        } else {
+            let mut child_game = child_game.unwrap_or_else(|| game.apply_move(pos));
+            let evaluation = evaluation.unwrap_or_else(|| child_game.evaluate_score());
+            search(self, &mut child_game, evaluation)
+        };
+        self.current_line.pop();
+        node_eval
+    }
+
+    /// The move is the new best at this ply, its line continues with the best line of the child
+    fn update_pv(&mut self, ply: usize, best_move: M) {
+        let (line, child_line) = self.pv_table.split_at_mut(ply + 1);
+        line[ply].clear();
+        line[ply].push(best_move);
+        line[ply].extend_from_slice(&child_line[0]);
+    }
+
+    fn _minimax(
+        &mut self,
+        game: &mut G,
+        current_depth: u32,
+        mut alfa: Score, // best for maximizing player
+        mut beta: Score, // best for minimizing player
+        score_eval: EvaluationScore,
+        pv_hint: &[M], // best line of the previous iteration, if still following it
+    ) -> NodeResult<M> {
+        let (hash_move, current_node_idx) =
+            match self.enter_node(game, current_depth, alfa, beta, &score_eval) {
+                ControlFlow::Break(result) => return result,
+                ControlFlow::Continue(expand) => expand,
+            };
+        if game.is_chance_node() {
+            return self._chance(
+                game,
+                current_depth,
+                alfa,
+                beta,
+                score_eval,
+                current_node_idx,
+            );
+        }
+        if let Some(score) = self.null_move_cutoff(game, current_depth, alfa, beta, &score_eval) {
+            return NodeResult { score, tree: None };
+        }
+        let ply = current_depth as usize;
+        let remaining_depth = self.remaining_depth(current_depth);
+        let (alfa_original, beta_original) = (alfa, beta);
+
+        let score_multiplier = game.get_current_player().score_multiplier();
+        let new_states = self.ordered_children(game, ply, hash_move, pv_hint);
 
+        // fallback if the search gets stopped before any move is done
+        let first_move = new_states.first().map(|(m, _, _)| *m);
         let mut best_move = None;
         let mut best_value = 0;
         let mut suboptimal_value = 0.; // TODO suboptimal doesn't work well with pruning, should probably remove
         let mut analized_moves = 0;
-        let mut child_results_map: HashMap<(usize, usize), NodeType> = Default::default();
+        let mut child_results_map: HashMap<M, NodeType<M>> = Default::default();
 
-        for (pos, game, evaluation) in new_states {
-            let node_eval = self._minimax(game.as_ref(), current_depth + 1, alfa, beta, evaluation);
-            if node_eval.score * score_multiplier >= best_value || best_move.is_none() {
+        for child in new_states {
+            let pos = child.0;
+            let child_hint = match pv_hint.split_first() {
+                Some((hint_move, rest)) if *hint_move == pos => rest,
+                _ => &[],
+            };
+            let (child_alfa, child_beta) = (score_to_child(alfa), score_to_child(beta));
+            // selective search works from the point of view of the player to move
+            let mover_alfa = if score_multiplier > 0 {
+                alfa
+            } else {
+                beta.saturating_neg()
+            };
+            let move_index = analized_moves;
+            let forcing = self.params.max_extension > 0 && game.is_forcing(pos);
+            let node_eval = self.visit_child(game, child, |this, child_game, evaluation| {
+                let extend = this.extends(forcing, child_game);
+                if move_index > 0 && !extend {
+                    if let Some(bound) = this.futility_bound(
+                        remaining_depth,
+                        &evaluation,
+                        score_multiplier,
+                        mover_alfa,
+                    ) {
+                        return NodeResult {
+                            score: bound * score_multiplier,
+                            tree: None,
+                        };
+                    }
+                }
+                let mut search = |this: &mut Self| {
+                    this._minimax(
+                        child_game,
+                        current_depth + 1,
+                        child_alfa,
+                        child_beta,
+                        evaluation,
+                        child_hint,
+                    )
+                };
+                if extend {
+                    return this.extended(search);
+                }
+                if this.reduces_late_move(move_index, remaining_depth) {
+                    let reduced = this.reduced(1, &mut search);
+                    // only trusted if it confirms the move is worse
+                    let value = score_to_parent(reduced.score) * score_multiplier;
+                    if value <= mover_alfa || this.aborted {
+                        return reduced;
+                    }
+                    this.selective_stats.reduction_researches += 1;
+                }
+                search(this)
+            });
+            if self.aborted {
+                break;
+            }
+            // wins that take longer are worth less
+            let value = score_to_parent(node_eval.score);
+            if value * score_multiplier > best_value || best_move.is_none() {
                 best_move = Some(pos);
-                best_value = node_eval.score * score_multiplier;
+                best_value = value * score_multiplier;
+                self.update_pv(ply, pos);
             }
-            suboptimal_value += node_eval.score as f32;
+            suboptimal_value += value as f32;
             if score_multiplier > 0 {
-                alfa = std::cmp::max(alfa, node_eval.score)
+                alfa = std::cmp::max(alfa, value)
             } else {
-                beta = std::cmp::min(beta, node_eval.score)
+                beta = std::cmp::min(beta, value)
+            }
+            if let Some(child_tree) = node_eval.tree {
+                child_results_map.insert(pos, child_tree);
             }
-            child_results_map.insert(pos, node_eval);
 
             analized_moves += 1;
             // break early to prune solutions that will never be taken
             if self.params.pruning_enabled && beta <= alfa {
                 // trace!("Pruning {}, {}", alfa, beta);
+                self.record_cutoff(ply, pos, remaining_depth, analized_moves == 1);
                 break;
             }
         }
         if analized_moves > 0 {
             suboptimal_value /= analized_moves as f32;
         }
-
+        if best_move.is_none() && self.aborted {
+            best_move = first_move;
+            self.pv_table[ply].extend(first_move);
+        }
 
         let score_final = if analized_moves == 0 {
-            // could this break when using heuristics?
-            score_eval.score
+            // nobody won and there are no moves left
+            0
         } else {
-            (((best_value * score_multiplier) as f32 * (1. - self.params.weight_suboptimal)
-                + suboptimal_value * self.params.weight_suboptimal)
-                * self.params.depth_factor) as Score
+            ((best_value * score_multiplier) as f32 * (1. - self.params.weight_suboptimal)
+                + suboptimal_value * self.params.weight_suboptimal) as Score
         };
 
         // return the tree node
-        let node = Rc::new(DecisionTreeNode {
-            best_move: best_move,
-            score: score_final,
-            moves: child_results_map,
-            alfa: alfa,
-            beta: beta,
-            estimate: score_eval.score,
-            visit_order: current_node_idx,
+        let tree = self.params.build_tree.then(|| {
+            Rc::new(DecisionTreeNode {
+                best_move,
+                score: score_final,
+                moves: child_results_map,
+                alfa,
+                beta,
+                estimate: score_eval.score,
+                visit_order: current_node_idx,
+            })
         });
         debug!("Minimax in node: \n{:?}", game);
-        debug!("Node: {:?}", node);
-        // TODO big bug for progressive deepening: cache size is bigger than examined nodes.
-        // the cache will keep nodes that have been pruned away, but the result might change based on what the root node is, and thus the starting move
-        // cache could be invalidated on each run as a simple solution, but maybe some cache values on the selected branches can be kept
-        // need to dive deeper into this
-        self.cache.insert(cache_key, node.clone());
-        node
+        debug!("Node: score {} best_move {:?}", score_final, best_move);
+        // the best child tells if the search failed high or low against the original window.
+        // Pruned nodes are stored only as bounds
+        let bound = if analized_moves == 0 {
+            Bound::Exact
+        } else {
+            Bound::from_window(score_final, alfa_original, beta_original)
+        };
+        self.cache_node(game, score_final, best_move, &tree, remaining_depth, bound);
+        NodeResult {
+            score: score_final,
+            tree,
+        }
+    }
+
+    /// Same search as [`Minimax::_minimax`] with scores from the point of view of the player to move.
+    /// Moves after the first are only searched with a zero window, to prove they are not better.
+    /// See https://www.chessprogramming.org/Principal_Variation_Search
+    fn _negamax(
+        &mut self,
+        game: &mut G,
+        current_depth: u32,
+        mut alfa: Score,
+        beta: Score,
+        score_eval: EvaluationScore,
+        pv_hint: &[M],
+    ) -> NodeResult<M> {
+        // the parents route chance nodes to Minimax::_chance, only the root of MTD(f) can't
+        assert!(
+            !game.is_chance_node(),
+            "SearchAlgorithm::Mtdf can't start the search at a chance node"
+        );
+        // the cache and the tree keep the scores from X's point of view.
+        // Windows can hold Score::MIN after going through score_to_child, so negations saturate
+        let color = game.get_current_player().score_multiplier();
+        let (x_alfa, x_beta) = if color > 0 {
+            (alfa, beta)
+        } else {
+            (beta.saturating_neg(), alfa.saturating_neg())
+        };
+        let (hash_move, current_node_idx) =
+            match self.enter_node(game, current_depth, x_alfa, x_beta, &score_eval) {
+                ControlFlow::Break(result) => {
+                    return NodeResult {
+                        score: result.score * color,
+                        tree: result.tree,
+                    }
+                }
+                ControlFlow::Continue(expand) => expand,
+            };
+        if let Some(score) = self.null_move_cutoff(game, current_depth, x_alfa, x_beta, &score_eval)
+        {
+            return NodeResult {
+                score: score * color,
+                tree: None,
+            };
+        }
+        let ply = current_depth as usize;
+        let remaining_depth = self.remaining_depth(current_depth);
+
+        let new_states = self.ordered_children(game, ply, hash_move, pv_hint);
+        let first_move = new_states.first().map(|(m, _, _)| *m);
+        let mut best_move = None;
+        let mut best_value = -Score::MAX;
+        let mut analized_moves = 0;
+        let mut child_results_map: HashMap<M, NodeType<M>> = Default::default();
+
+        for child in new_states {
+            let pos = child.0;
+            let child_hint = match pv_hint.split_first() {
+                Some((hint_move, rest)) if *hint_move == pos => rest,
+                _ => &[],
+            };
+            let move_index = analized_moves;
+            let zero_window = move_index > 0 && self.params.pruning_enabled;
+            let forcing = self.params.max_extension > 0 && game.is_forcing(pos);
+            let node_eval = self.visit_child(game, child, |this, child_game, evaluation| {
+                let extend = this.extends(forcing, child_game);
+                if move_index > 0 && !extend {
+                    if let Some(bound) =
+                        this.futility_bound(remaining_depth, &evaluation, color, alfa)
+                    {
+                        return NodeResult {
+                            score: -bound,
+                            tree: None,
+                        };
+                    }
+                }
+                let reduce =
+                    zero_window && !extend && this.reduces_late_move(move_index, remaining_depth);
+                let mut search = |this: &mut Self| {
+                    let depth = current_depth + 1;
+                    let (child_alfa, child_beta) = (
+                        score_to_child(beta.saturating_neg()),
+                        score_to_child(alfa.saturating_neg()),
+                    );
+                    if !zero_window {
+                        return this.negamax_child(
+                            child_game, depth, child_alfa, child_beta, evaluation, child_hint,
+                            color,
+                        );
+                    }
+                    let zero_alfa = score_to_child(alfa.saturating_neg().saturating_sub(1));
+                    if reduce {
+                        let probe = this.reduced(1, |this| {
+                            this.negamax_child(
+                                child_game, depth, zero_alfa, child_beta, evaluation, child_hint,
+                                color,
+                            )
+                        });
+                        // only trusted if it confirms the move is worse
+                        if -score_to_parent(probe.score) <= alfa || this.aborted {
+                            return probe;
+                        }
+                        this.selective_stats.reduction_researches += 1;
+                    }
+                    let probe = this.negamax_child(
+                        child_game, depth, zero_alfa, child_beta, evaluation, child_hint, color,
+                    );
+                    let value = -score_to_parent(probe.score);
+                    if value > alfa && value < beta && !this.aborted {
+                        // better than the first move after all, needs the exact score
+                        this.ordering_stats.researches += 1;
+                        this.negamax_child(
+                            child_game, depth, child_alfa, child_beta, evaluation, child_hint,
+                            color,
+                        )
+                    } else {
+                        probe
+                    }
+                };
+                if extend {
+                    this.extended(search)
+                } else {
+                    search(this)
+                }
+            });
+            if self.aborted {
+                break;
+            }
+            // wins that take longer are worth less
+            let value = -score_to_parent(node_eval.score);
+            if value > best_value || best_move.is_none() {
+                best_move = Some(pos);
+                best_value = value;
+                self.update_pv(ply, pos);
+            }
+            alfa = std::cmp::max(alfa, value);
+            if let Some(child_tree) = node_eval.tree {
+                child_results_map.insert(pos, child_tree);
+            }
+
+            analized_moves += 1;
+            if self.params.pruning_enabled && alfa >= beta {
+                self.record_cutoff(ply, pos, remaining_depth, analized_moves == 1);
+                break;
+            }
+        }
+        if best_move.is_none() && self.aborted {
+            best_move = first_move;
+            self.pv_table[ply].extend(first_move);
+        }
+
+        // nobody won and there are no moves left
+        let score = if analized_moves == 0 { 0 } else { best_value };
+        let x_score = score * color;
+        let tree = self.params.build_tree.then(|| {
+            Rc::new(DecisionTreeNode {
+                best_move,
+                score: x_score,
+                moves: child_results_map,
+                alfa: x_alfa,
+                beta: x_beta,
+                estimate: score_eval.score,
+                visit_order: current_node_idx,
+            })
+        });
+        let bound = if analized_moves == 0 {
+            Bound::Exact
+        } else {
+            Bound::from_window(x_score, x_alfa, x_beta)
+        };
+        self.cache_node(game, x_score, best_move, &tree, remaining_depth, bound);
+        NodeResult { score, tree }
     }
+
+    /// Searches a child of a negamax node where the player with `color` moves.
+    /// Chance nodes have nobody to move, so they're searched from X's point of view by [`Minimax::_minimax`]
+    /// and their score is turned to the one of the opponent, like for any other child
+    #[allow(clippy::too_many_arguments)]
+    fn negamax_child(
+        &mut self,
+        game: &mut G,
+        current_depth: u32,
+        alfa: Score,
+        beta: Score,
+        score_eval: EvaluationScore,
+        pv_hint: &[M],
+        color: Score,
+    ) -> NodeResult<M> {
+        if !game.is_chance_node() {
+            return self._negamax(game, current_depth, alfa, beta, score_eval, pv_hint);
+        }
+        let child_color = -color;
+        let (x_alfa, x_beta) = if child_color > 0 {
+            (alfa, beta)
+        } else {
+            (beta.saturating_neg(), alfa.saturating_neg())
+        };
+        let result = self._minimax(game, current_depth, x_alfa, x_beta, score_eval, pv_hint);
+        NodeResult {
+            score: result.score * child_color,
+            tree: result.tree,
+        }
+    }
+
+    /// Expected score of the outcomes from X's point of view, chance nodes count as a ply like the others.
+    /// With pruning, a score outside the window is only a bound
+    fn _chance(
+        &mut self,
+        game: &mut G,
+        current_depth: u32,
+        alfa: Score,
+        beta: Score,
+        score_eval: EvaluationScore,
+        current_node_idx: u128,
+    ) -> NodeResult<M> {
+        let remaining_depth = self.remaining_depth(current_depth);
+        let pruning = self.params.chance_pruning;
+        let outcomes = game.chance_outcomes();
+        let (low, high) = self.params.score_bounds;
+        // what each outcome can still be worth, until it's searched
+        let mut lower = vec![low as f64; outcomes.len()];
+        let mut upper = vec![high as f64; outcomes.len()];
+        let mut cutoff = None;
+        if pruning == ChancePruning::Star2 {
+            cutoff = self.probe_outcomes(
+                game,
+                current_depth,
+                (alfa, beta),
+                &outcomes,
+                (&mut lower, &mut upper),
+            );
+        }
+        let weighted = |bounds: &[f64], from: usize| -> f64 {
+            outcomes[from..]
+                .iter()
+                .zip(&bounds[from..])
+                .map(|((_, probability), bound)| probability * bound)
+                .sum()
+        };
+        let (alfa_f, beta_f) = (alfa as f64, beta as f64);
+        let mut expected = 0.;
+        let mut child_results_map: HashMap<M, NodeType<M>> = Default::default();
+        for (i, &(outcome, probability)) in outcomes.iter().enumerate() {
+            if cutoff.is_some() {
+                break;
+            }
+            let (rest_lower, rest_upper) = (weighted(&lower, i + 1), weighted(&upper, i + 1));
+            // the outcome decides the expectation if it ends up outside of this window
+            let (child_alfa, child_beta) = match pruning {
+                ChancePruning::None => (Score::MIN, Score::MAX),
+                _ => (
+                    ((alfa_f - expected - rest_upper) / probability).floor() as Score,
+                    ((beta_f - expected - rest_lower) / probability).ceil() as Score,
+                ),
+            };
+            let node_eval = self.visit_child(
+                game,
+                (outcome, None, None),
+                |this, child_game, evaluation| {
+                    this._minimax(
+                        child_game,
+                        current_depth + 1,
+                        score_to_child(child_alfa),
+                        score_to_child(child_beta),
+                        evaluation,
+                        &[],
+                    )
+                },
+            );
+            if self.aborted {
+                break;
+            }
+            expected += probability * score_to_parent(node_eval.score) as f64;
+            if let Some(child_tree) = node_eval.tree {
+                child_results_map.insert(outcome, child_tree);
+            }
+            if pruning != ChancePruning::None {
+                if expected + rest_lower >= beta_f {
+                    cutoff = Some(expected + rest_lower);
+                } else if expected + rest_upper <= alfa_f {
+                    cutoff = Some(expected + rest_upper);
+                }
+            }
+        }
+        // bounds are rounded towards the outside of the window so they stay bounds
+        let score = match cutoff {
+            Some(bound) if bound >= beta_f => bound.floor() as Score,
+            Some(bound) => bound.ceil() as Score,
+            None => expected.round() as Score,
+        };
+        if cutoff.is_some() {
+            self.selective_stats.chance_cutoffs += 1;
+        }
+        let tree = self.params.build_tree.then(|| {
+            Rc::new(DecisionTreeNode {
+                best_move: None,
+                score,
+                moves: child_results_map,
+                alfa,
+                beta,
+                estimate: score_eval.score,
+                visit_order: current_node_idx,
+            })
+        });
+        let bound = match pruning {
+            ChancePruning::None => Bound::Exact,
+            _ => Bound::from_window(score, alfa, beta),
+        };
+        self.cache_node(game, score, None, &tree, remaining_depth, bound);
+        NodeResult { score, tree }
+    }
+
+    /// Star2 probing: searches a single move after every outcome. If the same player moves after all of them,
+    /// that move bounds what the outcome is worth: from below when X moves, from above when O does.
+    /// Gives the bound on the expectation if that's already enough for a cutoff
+    fn probe_outcomes(
+        &mut self,
+        game: &mut G,
+        current_depth: u32,
+        (alfa, beta): (Score, Score),
+        outcomes: &[(M, f64)],
+        (lower, upper): (&mut [f64], &mut [f64]),
+    ) -> Option<f64> {
+        // the outcomes need a move and the move needs depth left to be searched
+        if self.remaining_depth(current_depth) < 2 {
+            return None;
+        }
+        let mut players = outcomes.iter().map(|&(outcome, _)| {
+            let child_game = game.apply_move(outcome);
+            let searchable =
+                !child_game.is_chance_node() && !child_game.evaluate_score().is_terminal;
+            searchable.then(|| child_game.get_current_player())
+        });
+        let player = players.next()??;
+        if !players.all(|p| p == Some(player)) {
+            return None;
+        }
+        let (low, high) = self.params.score_bounds;
+        let weighted = |bounds: &[f64]| -> f64 {
+            outcomes
+                .iter()
+                .zip(bounds)
+                .map(|((_, probability), bound)| probability * bound)
+                .sum()
+        };
+        for (i, &(outcome, probability)) in outcomes.iter().enumerate() {
+            // window the probe needs to reach for a cutoff, with the other outcomes at their bounds
+            let window = if player == Player::X {
+                let others = weighted(lower) - probability * lower[i];
+                (low, ((beta as f64 - others) / probability).ceil() as Score)
+            } else {
+                let others = weighted(upper) - probability * upper[i];
+                (
+                    ((alfa as f64 - others) / probability).floor() as Score,
+                    high,
+                )
+            };
+            let probe = self.visit_child(game, (outcome, None, None), |this, child_game, _| {
+                let ply = current_depth as usize + 1;
+                let first = this
+                    .ordered_children(child_game, ply, None, &[])
+                    .into_iter()
+                    .next()?;
+                let probe = this.visit_child(child_game, first, |this, probe_game, evaluation| {
+                    this._minimax(
+                        probe_game,
+                        current_depth + 2,
+                        score_to_child(score_to_child(window.0)),
+                        score_to_child(score_to_child(window.1)),
+                        evaluation,
+                        &[],
+                    )
+                });
+                Some(score_to_parent(score_to_parent(probe.score)))
+            });
+            if self.aborted {
+                return None;
+            }
+            let Some(probe) = probe else {
+                continue;
+            };
+            self.selective_stats.chance_probes += 1;
+            if player == Player::X {
+                lower[i] = lower[i].max(probe as f64);
+                if weighted(lower) >= beta as f64 {
+                    return Some(weighted(lower));
+                }
+            } else {
+                upper[i] = upper[i].min(probe as f64);
+                if weighted(upper) <= alfa as f64 {
+                    return Some(weighted(upper));
+                }
+            }
+        }
+        None
+    }
+
+    /// Searches the node with the window, scores from X's point of view whatever the algorithm
+    fn search_window(
+        &mut self,
+        game: &mut G,
+        current_depth: u32,
+        alfa: Score,
+        beta: Score,
+        evaluation: EvaluationScore,
+        pv_hint: &[M],
+    ) -> NodeResult<M> {
+        if self.params.algorithm == SearchAlgorithm::Minimax || game.is_chance_node() {
+            return self._minimax(game, current_depth, alfa, beta, evaluation, pv_hint);
+        }
+        // negamax scores are from the point of view of the player to move
+        let color = game.get_current_player().score_multiplier();
+        let (alfa, beta) = if color > 0 {
+            (alfa, beta)
+        } else {
+            (beta.saturating_neg(), alfa.saturating_neg())
+        };
+        let root = self._negamax(game, current_depth, alfa, beta, evaluation, pv_hint);
+        NodeResult {
+            score: root.score * color,
+            tree: root.tree,
+        }
+    }
+
+    /// Searches a narrow window around the guess, which prunes more if the guess is right.
+    /// A score outside the window is only a bound, so the search is repeated with a wider one
+    fn aspiration_search(
+        &mut self,
+        game: &mut G,
+        guess: Score,
+        window: Score,
+        pv_hint: &[M],
+    ) -> NodeResult<M> {
+        let mut delta = window.max(1);
+        let mut alfa = guess.saturating_sub(delta);
+        let mut beta = guess.saturating_add(delta);
+        loop {
+            let evaluation = game.evaluate_score();
+            let root = self.search_window(game, 0, alfa, beta, evaluation, pv_hint);
+            if self.aborted {
+                return root;
+            }
+            // grow the window faster every time, in case the guess was way off
+            delta = delta.saturating_mul(2);
+            if root.score <= alfa && alfa > Score::MIN {
+                alfa = root.score.saturating_sub(delta);
+            } else if root.score >= beta && beta < Score::MAX {
+                beta = root.score.saturating_add(delta);
+            } else {
+                return root;
+            }
+            debug!("Aspiration window failed, searching ({}, {})", alfa, beta);
+            self.ordering_stats.aspiration_researches += 1;
+        }
+    }
+
+    /// Zero window searches around the guess until the upper and lower bounds meet.
+    /// Scores are from the point of view of the player to move, like in negamax
+    fn mtdf(&mut self, game: &mut G, guess: Score, pv_hint: &[M]) -> NodeResult<M> {
+        let mut score = guess;
+        let (mut lower, mut upper) = (-Score::MAX, Score::MAX);
+        // only searches that fail high prove a move is as good as the score
+        let mut best = None;
+        while lower < upper {
+            let beta = if score == lower { score + 1 } else { score };
+            let root = self._negamax(game, 0, beta - 1, beta, game.evaluate_score(), pv_hint);
+            if self.aborted {
+                break;
+            }
+            score = root.score;
+            if score < beta {
+                upper = score;
+            } else {
+                lower = score;
+                best = Some((root, self.pv_table[0].clone()));
+            }
+        }
+        match best {
+            Some((root, line)) => {
+                self.pv_table[0] = line;
+                root
+            }
+            // interrupted before anything failed high
+            None => NodeResult { score, tree: None },
+        }
+    }
+
+    /// Depth still to search below the node, changed by the reductions and extensions on the line
+    fn remaining_depth(&self, current_depth: u32) -> u32 {
+        (self.search_depth + self.extension).saturating_sub(current_depth + self.reduction)
+    }
+
+    /// Runs the search with `plies` less depth
+    fn reduced<T>(&mut self, plies: u32, search: impl FnOnce(&mut Self) -> T) -> T {
+        self.reduction += plies;
+        let result = search(self);
+        self.reduction -= plies;
+        result
+    }
+
+    /// Lets the opponent play twice, with a shallower search. If the position is still good enough for a cutoff,
+    /// a real move would most likely be too. Window and score from X's point of view
+    fn null_move_cutoff(
+        &mut self,
+        game: &mut G,
+        current_depth: u32,
+        alfa: Score,
+        beta: Score,
+        score_eval: &EvaluationScore,
+    ) -> Option<Score> {
+        let reduction = self.params.null_move_reduction?;
+        let maximizing = game.get_current_player().score_multiplier() > 0;
+        // only worth trying if the position already looks good enough, and never to prove a win
+        let promising = if maximizing {
+            score_eval.score >= beta && beta < WIN_THRESHOLD
+        } else {
+            score_eval.score <= alfa && alfa > -WIN_THRESHOLD
+        };
+        if current_depth == 0
+            || self.in_null_move
+            || !promising
+            || self.remaining_depth(current_depth) < reduction + 2
+            || !game.null_move_allowed()
+        {
+            return None;
+        }
+        // only needs to know which side of the bound the score is
+        let (null_alfa, null_beta) = if maximizing {
+            (beta - 1, beta)
+        } else {
+            (alfa, alfa + 1)
+        };
+        game.make_null_move();
+        self.in_null_move = true;
+        let evaluation = game.evaluate_score();
+        let result = self.reduced(reduction, |this| {
+            this.search_window(
+                game,
+                current_depth + 1,
+                score_to_child(null_alfa),
+                score_to_child(null_beta),
+                evaluation,
+                &[],
+            )
+        });
+        self.in_null_move = false;
+        game.unmake_null_move();
+        let score = score_to_parent(result.score);
+        let cutoff = if maximizing {
+            score >= beta
+        } else {
+            score <= alfa
+        };
+        if !cutoff || self.aborted {
+            return None;
+        }
+        self.selective_stats.null_move_cutoffs += 1;
+        // wins found after passing are not real, only the bound is
+        Some(if maximizing { beta } else { alfa })
+    }
+
+    /// Whether the move leading to the child game should be searched one ply deeper
+    fn extends(&mut self, forcing: bool, child_game: &G) -> bool {
+        let extends =
+            self.extension < self.params.max_extension && (forcing || child_game.is_unstable());
+        if extends {
+            self.selective_stats.extensions += 1;
+        }
+        extends
+    }
+
+    /// Runs the search one ply deeper
+    fn extended<T>(&mut self, search: impl FnOnce(&mut Self) -> T) -> T {
+        self.extension += 1;
+        let result = search(self);
+        self.extension -= 1;
+        result
+    }
+
+    fn reduces_late_move(&mut self, move_index: usize, remaining_depth: u32) -> bool {
+        let reduces = matches!(self.params.late_move_reduction,
+            Some(start) if move_index >= start && remaining_depth >= 3);
+        if reduces {
+            self.selective_stats.reductions += 1;
+        }
+        reduces
+    }
+
+    /// Near the leaves, a move evaluated far below alfa is not worth searching.
+    /// Gives the best the move can hope for. Scores from the point of view of the player making the move
+    fn futility_bound(
+        &mut self,
+        remaining_depth: u32,
+        evaluation: &EvaluationScore,
+        color: Score,
+        alfa: Score,
+    ) -> Option<Score> {
+        const FUTILITY_DEPTH: u32 = 2;
+        let margin = self.params.futility_margin?;
+        if remaining_depth > FUTILITY_DEPTH || evaluation.is_terminal {
+            return None;
+        }
+        let bound = (evaluation.score * color).saturating_add(margin * remaining_depth as Score);
+        if bound > alfa {
+            return None;
+        }
+        self.selective_stats.futility_prunes += 1;
+        Some(bound)
+    }
+
+    fn uses_cache(&self) -> bool {
+        self.params.cache_enabled || self.mtdf_table.is_some()
+    }
+
+    /// Table the search reads and writes
+    fn table(&self) -> &TranspositionTable<M, G::Hash> {
+        self.mtdf_table.as_ref().unwrap_or(&self.cache)
+    }
+
+    fn table_mut(&mut self) -> &mut TranspositionTable<M, G::Hash> {
+        self.mtdf_table.as_mut().unwrap_or(&mut self.cache)
+    }
+
+    fn record_cutoff(
+        &mut self,
+        ply: usize,
+        cutoff_move: M,
+        remaining_depth: u32,
+        first_move: bool,
+    ) {
+        self.ordering_stats.cutoffs += 1;
+        if first_move {
+            self.ordering_stats.first_move_cutoffs += 1;
+        }
+        if self.params.killer_moves {
+            if self.killers.len() <= ply {
+                self.killers.resize(ply + 1, [None, None]);
+            }
+            let killers = &mut self.killers[ply];
+            if killers[0] != Some(cutoff_move) {
+                killers[1] = killers[0];
+                killers[0] = Some(cutoff_move);
+            }
+        }
+        if self.params.history_heuristic {
+            *self.history.entry(cutoff_move).or_default() +=
+                (remaining_depth * remaining_depth) as u64;
+        }
+        if self.params.counter_moves {
+            if let Some(&last_move) = self.current_line.last() {
+                self.counter_moves.insert(last_move, cutoff_move);
+            }
+        }
+    }
+
+    fn cache_node(
+        &mut self,
+        game: &G,
+        score: Score,
+        best_move: Option<M>,
+        tree: &Option<NodeType<M>>,
+        depth: u32,
+        bound: Bound,
+    ) {
+        if !self.uses_cache() || self.aborted {
+            return;
+        }
+        self.table_mut().insert(
+            game.get_hash(),
+            TranspositionEntry {
+                score,
+                best_move,
+                depth,
+                bound,
+                node: tree.clone(),
+                verification: if cfg!(debug_assertions) {
+                    game.get_verification_hash()
+                } else {
+                    None
+                },
+            },
+        );
+    }
+}
+
+/// Root moves of one depth, shared by the threads of [`Minimax::minimax_parallel`]
+struct RootSplit<G: MinimaxDriver> {
+    game: G,
+    moves: Vec<G::Move>,
+    depth: u32,
+    // soft deadline of the iterative deepening
+    deadline: Option<Instant>,
+    next_move: AtomicUsize,
+    // best score so far, from the point of view of the player to move
+    best_score: AtomicI32,
+}
+
+enum WorkerJob<G: MinimaxDriver> {
+    NewSearch,
+    Search(Arc<RootSplit<G>>),
+}
+
+/// Score, whether it's exact and line of every root move searched, nodes examined and if it was stopped
+type WorkerReport<M> = (Vec<(Score, bool, Vec<M>)>, u128, bool);
+
+/// Thread with its own [`Minimax`], which keeps its cache from one search to the next
+struct Worker<G: MinimaxDriver> {
+    jobs: Sender<WorkerJob<G>>,
+    reports: Receiver<WorkerReport<G::Move>>,
+}
+
+impl<G, M> Worker<G>
+where
+    G: MinimaxDriver<Move = M> + Send + Sync + 'static,
+    M: GameMove + Send + Sync + 'static,
+{
+    fn spawn(params: MinimaxParams, stop_handle: StopHandle) -> Self {
+        let (jobs, job_receiver) = mpsc::channel();
+        let (report_sender, reports) = mpsc::channel();
+        // ends when the sender is dropped along with the search that started it
+        thread::spawn(move || {
+            let mut minimax = Minimax::<G>::new(params);
+            minimax.stop_handle = stop_handle;
+            for job in job_receiver {
+                match job {
+                    WorkerJob::NewSearch => minimax.start_search(),
+                    WorkerJob::Search(split) => {
+                        if report_sender.send(minimax.search_split(&split)).is_err() {
+                            break;
+                        }
+                    }
+                }
+            }
+        });
+        Self { jobs, reports }
+    }
+}
+
+impl<G, M> Minimax<G>
+where
+    G: MinimaxDriver<Move = M> + Send + Sync + 'static,
+    M: GameMove + Send + Sync + 'static,
+{
+    /// Splits the root moves between [`MinimaxParams::threads`] threads, each with its own share of the cache.
+    /// The best score found so far is shared, so the other threads only need to prove their moves are worse.
+    /// The threads are started by the first call and kept for the next ones.
+    /// Finds the same score as [`Minimax::minimax`], but never builds the tree.
+    /// Root moves are searched with plain windows: [`SearchAlgorithm::Mtdf`] searches them like
+    /// [`SearchAlgorithm::NegamaxPvs`] and [`MinimaxParams::aspiration_window`] is not used
+    pub fn minimax_parallel(&mut self, game: &G) -> SearchResult<M> {
+        if self.params.threads > 1 && self.workers.is_empty() {
+            let threads = self.params.threads;
+            let params = MinimaxParams {
+                threads: 1,
+                build_tree: false,
+                time_budget: None,
+                max_nodes: self.params.max_nodes.map(|nodes| nodes / threads as u128),
+                cache_size_mb: (self.params.cache_size_mb / threads).max(1),
+                ..self.params.clone()
+            };
+            self.workers = (0..threads)
+                .map(|_| Worker::spawn(params.clone(), self.stop_handle.clone()))
+                .collect();
+        }
+        for worker in &self.workers {
+            worker.jobs.send(WorkerJob::NewSearch).unwrap();
+        }
+        self.run(game, Self::parallel_search)
+    }
+
+    fn parallel_search(
+        &mut self,
+        game: &G,
+        depth: u32,
+        pv_hint: &[M],
+        guess: Option<Score>,
+    ) -> SearchResult<M> {
+        let mut moves: Vec<M> = game.get_possible_moves().collect();
+        if self.workers.is_empty() || moves.len() <= 1 {
+            return self.search(game, depth, pv_hint, guess);
+        }
+        // the previous best move sets the first bound for the others
+        if let Some(idx) = pv_hint
+            .first()
+            .and_then(|first| moves.iter().position(|m| m == first))
+        {
+            moves[..=idx].rotate_right(1);
+        }
+        let color = game.get_current_player().score_multiplier();
+        let split = Arc::new(RootSplit {
+            game: game.clone(),
+            moves,
+            depth,
+            deadline: self.deadline,
+            next_move: AtomicUsize::new(0),
+            best_score: AtomicI32::new(-Score::MAX),
+        });
+        for worker in &self.workers {
+            worker.jobs.send(WorkerJob::Search(split.clone())).unwrap();
+        }
+        let reports: Vec<_> = self
+            .workers
+            .iter()
+            .map(|worker| worker.reports.recv().unwrap())
+            .collect();
+
+        let mut interrupted = false;
+        let mut best: Option<(Score, bool, Vec<M>)> = None;
+        for (results, nodes, aborted) in reports {
+            self.nodes_examined_total += nodes;
+            interrupted |= aborted;
+            for (score, exact, line) in results {
+                let better = match &best {
+                    None => true,
+                    Some((best_score, best_exact, _)) => {
+                        score * color > best_score * color
+                            || (score == *best_score && exact && !best_exact)
+                    }
+                };
+                if better {
+                    best = Some((score, exact, line));
+                }
+            }
+        }
+        let (score, _, principal_variation) =
+            best.unwrap_or_else(|| (0, false, split.moves[..1].to_vec()));
+        let res = SearchResult {
+            best_move: principal_variation.first().copied(),
+            score,
+            principal_variation,
+            depth,
+            nodes_examined: self.nodes_examined_total - self.nodes_examined_at_start,
+            interrupted,
+            tree: None,
+        };
+        if !res.interrupted {
+            self.report_progress(&res);
+        }
+        res
+    }
+}
+
+impl<G: MinimaxDriver<Move = M>, M: GameMove> Minimax<G> {
+    /// Searches the game after one of the root moves, for the parallel search.
+    /// Only needs an exact score if the move is better than `bound`, from the point of view of the player to move.
+    /// Gives the score from X's point of view and the line starting with the move, if not stopped
+    /// Takes root moves from the split until there are none left
+    fn search_split(&mut self, split: &RootSplit<G>) -> WorkerReport<M> {
+        self.aborted = false;
+        self.deadline = split.deadline;
+        // the node limit is split between the threads for every depth
+        self.nodes_examined_at_start = self.nodes_examined_total;
+        let color = split.game.get_current_player().score_multiplier();
+        let mut results = vec![];
+        while let Some(&root_move) = split
+            .moves
+            .get(split.next_move.fetch_add(1, Ordering::Relaxed))
+        {
+            let bound = split.best_score.load(Ordering::Relaxed);
+            let Some((score, line)) =
+                self.search_root_move(&split.game, root_move, split.depth, color, bound)
+            else {
+                break;
+            };
+            split.best_score.fetch_max(score * color, Ordering::Relaxed);
+            // only scores above the bound are exact, the others are upper bounds
+            results.push((score, score * color > bound, line));
+        }
+        let nodes = self.nodes_examined_total - self.nodes_examined_at_start;
+        (results, nodes, self.aborted)
+    }
+
+    fn search_root_move(
+        &mut self,
+        game: &G,
+        root_move: M,
+        depth: u32,
+        color: Score,
+        bound: Score,
+    ) -> Option<(Score, Vec<M>)> {
+        self.search_depth = depth;
+        let mut child = game.apply_move(root_move);
+        let evaluation = child.evaluate_score();
+        let (alfa, beta) = if color > 0 {
+            (bound, Score::MAX)
+        } else {
+            (Score::MIN, -bound)
+        };
+        self.current_line.push(root_move);
+        let result = self.search_window(
+            &mut child,
+            1,
+            score_to_child(alfa),
+            score_to_child(beta),
+            evaluation,
+            &[],
+        );
+        self.current_line.pop();
+        if self.aborted {
+            return None;
+        }
+        let mut line = vec![root_move];
+        line.extend_from_slice(&self.pv_table[1]);
+        self.extend_line_from_cache(game, &mut line);
+        Some((score_to_parent(result.score), line))
+    }
+}
+
+impl<G: MinimaxDriver<Move = M>, M: GameMove> SearchEngine<G> for Minimax<G> {
+    fn analyze(&mut self, game: &G) -> SearchResult<M> {
+        self.minimax(game)
+    }
+
+    fn set_time_budget(&mut self, budget: Option<Duration>) {
+        self.params.time_budget = budget;
+    }
+}
+
+/// Game states resulting from playing each of the moves in turn, eg. on the principal variation
+pub fn apply_moves<G: MinimaxDriver>(game: &G, moves: &[G::Move]) -> Vec<G> {
+    let mut states: Vec<G> = Vec::with_capacity(moves.len());
+    for &next_move in moves {
+        let next = match states.last() {
+            Some(state) => state.apply_move(next_move),
+            None => game.apply_move(next_move),
+        };
+        states.push(next);
+    }
+    states
 }
 
 impl Player {
@@ -216,7 +2054,7 @@ impl Player {
     }
 }
 
-impl core::fmt::Debug for DecisionTreeNode {
+impl<M: GameMove> core::fmt::Debug for DecisionTreeNode<M> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("DecisionTreeNode")
             .field("score", &self.score)
@@ -225,3 +2063,295 @@ impl core::fmt::Debug for DecisionTreeNode {
             .finish()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tictactoe::{self, TicTacToeGame};
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use rstest::*;
+
+    /// Every position gets the same hash
+    #[derive(Clone, Debug)]
+    struct CollidingGame(TicTacToeGame);
+
+    impl MinimaxDriver for CollidingGame {
+        type Move = tictactoe::Move;
+        type Hash = GameHash;
+
+        fn evaluate_score(&self) -> EvaluationScore {
+            self.0.evaluate_score()
+        }
+        fn get_possible_moves(&self) -> impl Iterator<Item = Self::Move> + '_ {
+            self.0.get_possible_moves()
+        }
+        fn make_move(&mut self, next_move: Self::Move) {
+            self.0.make_move(next_move)
+        }
+        fn unmake_move(&mut self, last_move: Self::Move) {
+            self.0.unmake_move(last_move)
+        }
+        fn get_hash(&self) -> GameHash {
+            0
+        }
+        fn get_verification_hash(&self) -> Option<GameHash> {
+            self.0.get_verification_hash()
+        }
+        fn get_current_player(&self) -> Player {
+            self.0.get_current_player()
+        }
+        fn has_ended(&self) -> bool {
+            self.0.has_ended()
+        }
+        fn get_winner(&self) -> Player {
+            self.0.get_winner()
+        }
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "Hash collision")]
+    fn test_detects_hash_collisions() {
+        let mut minimax = Minimax::new(MinimaxParams {
+            cache_enabled: true,
+            ..Default::default()
+        });
+        minimax.minimax(&CollidingGame(TicTacToeGame::default()));
+    }
+
+    #[test]
+    fn test_score_outcome() {
+        assert_eq!(
+            ScoreOutcome::from_score(win_score(Player::X, 3), Player::X),
+            ScoreOutcome::Win(3)
+        );
+        assert_eq!(
+            ScoreOutcome::from_score(win_score(Player::X, 3), Player::O),
+            ScoreOutcome::Loss(3)
+        );
+        assert_eq!(
+            ScoreOutcome::from_score(win_score(Player::O, 0), Player::O),
+            ScoreOutcome::Win(0)
+        );
+        assert_eq!(ScoreOutcome::from_score(0, Player::O), ScoreOutcome::Draw);
+        assert_eq!(
+            ScoreOutcome::from_score(-20, Player::O),
+            ScoreOutcome::Heuristic(20)
+        );
+    }
+
+    #[test]
+    fn test_wins_get_worse_up_the_tree() {
+        let win = win_score(Player::X, 0);
+        assert_eq!(score_to_parent(win), win_score(Player::X, 1));
+        assert_eq!(score_to_parent(-win), win_score(Player::O, 1));
+        assert_eq!(score_to_parent(50), 50);
+        assert_eq!(score_to_child(score_to_parent(win)), win);
+        assert_eq!(score_to_child(Score::MAX), Score::MAX);
+        assert_eq!(score_to_child(Score::MIN), Score::MIN);
+    }
+
+    #[derive(Debug)]
+    enum TreeNode {
+        Max(Vec<usize>),
+        Min(Vec<usize>),
+        Chance(Vec<(usize, f64)>),
+        Leaf(Score),
+    }
+
+    /// Explicit game tree, moves are the indexes of the nodes they lead to
+    #[derive(Clone, Debug)]
+    struct TreeGame {
+        nodes: Rc<Vec<TreeNode>>,
+        root: usize,
+        path: Vec<usize>,
+    }
+
+    impl TreeGame {
+        fn new(nodes: Vec<TreeNode>) -> Self {
+            Self {
+                root: nodes.len() - 1,
+                nodes: Rc::new(nodes),
+                path: vec![],
+            }
+        }
+
+        fn node(&self) -> &TreeNode {
+            &self.nodes[self.path.last().copied().unwrap_or(self.root)]
+        }
+
+        /// Levels from the root down, every node has `width` children and the last level is made of leaves
+        fn random(
+            seed: u64,
+            levels: &[fn(Vec<usize>, &mut StdRng) -> TreeNode],
+            width: usize,
+        ) -> Self {
+            fn build(
+                nodes: &mut Vec<TreeNode>,
+                rng: &mut StdRng,
+                levels: &[fn(Vec<usize>, &mut StdRng) -> TreeNode],
+                width: usize,
+            ) -> usize {
+                let node = match levels.split_first() {
+                    Some((level, rest)) => {
+                        let children = (0..width).map(|_| build(nodes, rng, rest, width)).collect();
+                        level(children, rng)
+                    }
+                    None => TreeNode::Leaf(rng.gen_range(-100..=100)),
+                };
+                nodes.push(node);
+                nodes.len() - 1
+            }
+            let mut nodes = vec![];
+            build(&mut nodes, &mut StdRng::seed_from_u64(seed), levels, width);
+            Self::new(nodes)
+        }
+    }
+
+    fn chance(children: Vec<usize>, rng: &mut StdRng) -> TreeNode {
+        let weights: Vec<f64> = children.iter().map(|_| rng.gen_range(1.0..10.0)).collect();
+        let total: f64 = weights.iter().sum();
+        TreeNode::Chance(
+            children
+                .into_iter()
+                .zip(weights.iter().map(|w| w / total))
+                .collect(),
+        )
+    }
+
+    impl MinimaxDriver for TreeGame {
+        type Move = usize;
+        type Hash = GameHash;
+
+        fn evaluate_score(&self) -> EvaluationScore {
+            let score = match self.node() {
+                TreeNode::Leaf(score) => *score,
+                _ => 0,
+            };
+            EvaluationScore {
+                score,
+                is_terminal: false,
+            }
+        }
+        fn get_possible_moves(&self) -> impl Iterator<Item = Self::Move> + '_ {
+            let moves = match self.node() {
+                TreeNode::Max(children) | TreeNode::Min(children) => children.clone(),
+                TreeNode::Chance(outcomes) => outcomes.iter().map(|(m, _)| *m).collect(),
+                TreeNode::Leaf(_) => vec![],
+            };
+            moves.into_iter()
+        }
+        fn make_move(&mut self, next_move: Self::Move) {
+            self.path.push(next_move)
+        }
+        fn unmake_move(&mut self, _last_move: Self::Move) {
+            self.path.pop();
+        }
+        fn get_hash(&self) -> GameHash {
+            self.path.last().copied().unwrap_or(self.root) as GameHash
+        }
+        fn is_chance_node(&self) -> bool {
+            matches!(self.node(), TreeNode::Chance(_))
+        }
+        fn chance_outcomes(&self) -> Vec<(Self::Move, f64)> {
+            match self.node() {
+                TreeNode::Chance(outcomes) => outcomes.clone(),
+                _ => vec![],
+            }
+        }
+        fn get_current_player(&self) -> Player {
+            match self.node() {
+                TreeNode::Max(_) => Player::X,
+                TreeNode::Min(_) => Player::O,
+                _ => Player::None,
+            }
+        }
+        fn has_ended(&self) -> bool {
+            matches!(self.node(), TreeNode::Leaf(_))
+        }
+        fn get_winner(&self) -> Player {
+            Player::None
+        }
+    }
+
+    #[rstest]
+    fn test_expectiminimax(
+        #[values(ChancePruning::None, ChancePruning::Star1, ChancePruning::Star2)]
+        chance_pruning: ChancePruning,
+        #[values(
+            SearchAlgorithm::Minimax,
+            SearchAlgorithm::NegamaxPvs,
+            SearchAlgorithm::Mtdf
+        )]
+        algorithm: SearchAlgorithm,
+    ) {
+        // the first move has the best outcome, the second the best expectation
+        let game = TreeGame::new(vec![
+            TreeNode::Leaf(10),
+            TreeNode::Leaf(-6),
+            TreeNode::Chance(vec![(0, 0.5), (1, 0.5)]),
+            TreeNode::Leaf(4),
+            TreeNode::Leaf(0),
+            TreeNode::Chance(vec![(3, 0.75), (4, 0.25)]),
+            TreeNode::Max(vec![2, 5]),
+        ]);
+        let result = Minimax::new(MinimaxParams {
+            max_depth: 2,
+            algorithm,
+            chance_pruning,
+            score_bounds: (-10, 10),
+            ..Default::default()
+        })
+        .minimax(&game);
+        assert_eq!(result.best_move, Some(5));
+        assert_eq!(result.score, 3);
+        // the line stops where chance takes over
+        assert_eq!(result.principal_variation, vec![5]);
+    }
+
+    #[rstest]
+    fn test_chance_pruning(
+        #[values(0, 1, 2, 3)] seed: u64,
+        #[values(false, true)] cache_enabled: bool,
+    ) {
+        let game = TreeGame::random(
+            seed,
+            &[
+                |children, _| TreeNode::Max(children),
+                chance,
+                |children, _| TreeNode::Min(children),
+                chance,
+                |children, _| TreeNode::Max(children),
+            ],
+            4,
+        );
+        let search = |chance_pruning, algorithm| {
+            let mut minimax = Minimax::new(MinimaxParams {
+                max_depth: 5,
+                algorithm,
+                chance_pruning,
+                score_bounds: (-100, 100),
+                cache_enabled,
+                ..Default::default()
+            });
+            let result = minimax.minimax(&game);
+            (result, minimax.get_selective_stats())
+        };
+        let (expected, _) = search(ChancePruning::None, SearchAlgorithm::Minimax);
+        let (star1, star1_stats) = search(ChancePruning::Star1, SearchAlgorithm::Minimax);
+        let (star2, star2_stats) = search(ChancePruning::Star2, SearchAlgorithm::Minimax);
+        assert_eq!(star1.score, expected.score);
+        assert_eq!(star2.score, expected.score);
+        // chance nodes below negamax nodes are searched the same way
+        for algorithm in [SearchAlgorithm::NegamaxPvs, SearchAlgorithm::Mtdf] {
+            let (result, _) = search(ChancePruning::Star2, algorithm);
+            assert_eq!(result.score, expected.score);
+        }
+        assert!(star1_stats.chance_cutoffs > 0);
+        assert!(star1.nodes_examined < expected.nodes_examined);
+        assert!(star2_stats.chance_probes > 0);
+        if cache_enabled {
+            assert!(star2.nodes_examined < star1.nodes_examined);
+        }
+    }
+}
@@ -0,0 +1,194 @@
+use std::fmt::Debug;
+
+use crate::minimax::{GameMove, Score};
+
+/// Players are numbered from 0 up to [`MultiplayerDriver::player_count`], which is excluded
+pub type PlayerIndex = usize;
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct MultiEvaluation {
+    /// One per player, higher is better for that player
+    pub scores: Vec<Score>,
+    pub is_terminal: bool,
+}
+
+/// Games with any number of players, each one with their own score.
+/// [`crate::minimax::MinimaxDriver`] is for two player zero sum games, where one score is enough
+pub trait MultiplayerDriver: Clone + Debug {
+    type Move: GameMove;
+
+    fn player_count(&self) -> usize;
+    fn current_player(&self) -> PlayerIndex;
+    fn evaluate_scores(&self) -> MultiEvaluation;
+    fn get_possible_moves(&self) -> impl Iterator<Item = Self::Move> + '_;
+    fn make_move(&mut self, next_move: Self::Move);
+    /// Reverse of [`MultiplayerDriver::make_move`], same contract as [`crate::minimax::MinimaxDriver::unmake_move`]
+    fn unmake_move(&mut self, last_move: Self::Move);
+}
+
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum MultiplayerAlgorithm {
+    /// Every player plays for their own score. Can only prune with [`MultiplayerParams::max_score_sum`]
+    MaxN,
+    /// Everyone else plays against the player to move at the root, which makes it a two player game
+    /// with alpha beta pruning. Too pessimistic when the other players have their own goals
+    Paranoid,
+}
+
+pub struct MultiplayerParams {
+    pub max_depth: u32,
+    pub algorithm: MultiplayerAlgorithm,
+    /// Set if the scores are never negative and always add up to at most this.
+    /// Enables shallow pruning on [`MultiplayerAlgorithm::MaxN`]: a player that already has most of the total
+    /// leaves too little for the player before them
+    pub max_score_sum: Option<Score>,
+    pub pruning_enabled: bool,
+}
+
+impl Default for MultiplayerParams {
+    fn default() -> Self {
+        Self {
+            max_depth: 4,
+            algorithm: MultiplayerAlgorithm::MaxN,
+            max_score_sum: None,
+            pruning_enabled: true,
+        }
+    }
+}
+
+pub struct MultiplayerResult<M> {
+    pub best_move: Option<M>,
+    /// Scores at the end of the principal variation, one per player
+    pub scores: Vec<Score>,
+    pub principal_variation: Vec<M>,
+    pub nodes_examined: u128,
+}
+
+/// Line and scores found below a node
+type Line<M> = (Vec<Score>, Vec<M>);
+
+/// Search for [`MultiplayerDriver`] games. No cache or move ordering yet
+// TODO share the cache and the move ordering with Minimax
+pub struct MultiplayerSearch {
+    params: MultiplayerParams,
+    nodes_examined: u128,
+}
+
+impl MultiplayerSearch {
+    pub fn new(params: MultiplayerParams) -> Self {
+        Self {
+            params,
+            nodes_examined: 0,
+        }
+    }
+
+    pub fn search<G: MultiplayerDriver>(&mut self, game: &G) -> MultiplayerResult<G::Move> {
+        self.nodes_examined = 0;
+        let mut game = game.clone();
+        let depth = self.params.max_depth;
+        let (scores, mut principal_variation) = match self.params.algorithm {
+            MultiplayerAlgorithm::MaxN => self.max_n(&mut game, depth, None),
+            MultiplayerAlgorithm::Paranoid => {
+                let root_player = game.current_player();
+                let (_, line) =
+                    self.paranoid(&mut game, depth, root_player, Score::MIN, Score::MAX);
+                line
+            }
+        };
+        // lines are built from the leaves up
+        principal_variation.reverse();
+        MultiplayerResult {
+            best_move: principal_variation.first().copied(),
+            scores,
+            principal_variation,
+            nodes_examined: self.nodes_examined,
+        }
+    }
+
+    /// `parent_best` is the best score the player moving in the parent node has found so far
+    fn max_n<G: MultiplayerDriver>(
+        &mut self,
+        game: &mut G,
+        depth: u32,
+        parent_best: Option<Score>,
+    ) -> Line<G::Move> {
+        self.nodes_examined += 1;
+        let evaluation = game.evaluate_scores();
+        debug_assert_eq!(evaluation.scores.len(), game.player_count());
+        if evaluation.is_terminal || depth == 0 {
+            return (evaluation.scores, vec![]);
+        }
+        let player = game.current_player();
+        let moves: Vec<G::Move> = game.get_possible_moves().collect();
+        let mut best: Option<Line<G::Move>> = None;
+        for next_move in moves {
+            game.make_move(next_move);
+            let best_score = best.as_ref().map(|(scores, _)| scores[player]);
+            let (scores, mut line) = self.max_n(game, depth - 1, best_score);
+            game.unmake_move(next_move);
+            // the mover only switches to a later move for a strictly higher score of their own
+            if best_score.is_none_or(|best_score| scores[player] > best_score) {
+                line.push(next_move);
+                best = Some((scores, line));
+            }
+            // what's left for the parent's player is no better than what it already has
+            let best_score = best.as_ref().map(|(scores, _)| scores[player]);
+            if let (true, Some(sum), Some(parent_best), Some(best_score)) = (
+                self.params.pruning_enabled,
+                self.params.max_score_sum,
+                parent_best,
+                best_score,
+            ) {
+                if best_score >= sum - parent_best {
+                    break;
+                }
+            }
+        }
+        // nobody can move but the game is not over, the evaluation is all we have
+        best.unwrap_or((evaluation.scores, vec![]))
+    }
+
+    /// Alpha beta on the score of the root player, who is the only one maximizing
+    fn paranoid<G: MultiplayerDriver>(
+        &mut self,
+        game: &mut G,
+        depth: u32,
+        root_player: PlayerIndex,
+        mut alfa: Score,
+        mut beta: Score,
+    ) -> (Score, Line<G::Move>) {
+        self.nodes_examined += 1;
+        let evaluation = game.evaluate_scores();
+        debug_assert_eq!(evaluation.scores.len(), game.player_count());
+        if evaluation.is_terminal || depth == 0 {
+            return (evaluation.scores[root_player], (evaluation.scores, vec![]));
+        }
+        let maximizing = game.current_player() == root_player;
+        let moves: Vec<G::Move> = game.get_possible_moves().collect();
+        let mut best: Option<(Score, Line<G::Move>)> = None;
+        for next_move in moves {
+            game.make_move(next_move);
+            let (value, (scores, mut line)) =
+                self.paranoid(game, depth - 1, root_player, alfa, beta);
+            game.unmake_move(next_move);
+            let better = match &best {
+                None => true,
+                Some((best_value, _)) if maximizing => value > *best_value,
+                Some((best_value, _)) => value < *best_value,
+            };
+            if better {
+                line.push(next_move);
+                best = Some((value, (scores, line)));
+            }
+            if maximizing {
+                alfa = alfa.max(value);
+            } else {
+                beta = beta.min(value);
+            }
+            if self.params.pruning_enabled && beta <= alfa {
+                break;
+            }
+        }
+        best.unwrap_or((evaluation.scores[root_player], (evaluation.scores, vec![])))
+    }
+}
@@ -0,0 +1,317 @@
+use crate::{game::*, minimax::*};
+
+/// Game theoretic value of a position, for the player to move
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum ProofOutcome {
+    Win,
+    Loss,
+    Draw,
+    /// Ran out of nodes before proving anything
+    Unknown,
+}
+
+pub struct ProofNumberParams {
+    /// Limit on the nodes kept in the tree, over both searches. Every node stays in memory
+    pub max_nodes: Option<u128>,
+}
+
+impl Default for ProofNumberParams {
+    fn default() -> Self {
+        Self {
+            max_nodes: Some(1_000_000),
+        }
+    }
+}
+
+pub struct ProofResult<M> {
+    pub outcome: ProofOutcome,
+    /// Move that proves the outcome. The most promising one if unknown, any move if lost
+    pub best_move: Option<M>,
+    pub nodes_examined: u128,
+}
+
+type ProofNumber = u32;
+const INFINITY: ProofNumber = ProofNumber::MAX;
+
+/// What has to be proven for the player to move at the root
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+enum Target {
+    Win,
+    DrawOrBetter,
+}
+
+struct ProofNode<M> {
+    /// Move the parent plays to reach the node, the root has none
+    last_move: Option<M>,
+    children: Vec<usize>,
+    /// Least number of leaves that have to be proven to prove the node
+    proof: ProofNumber,
+    /// Least number of leaves that have to be disproven to disprove the node
+    disproof: ProofNumber,
+    /// The root player moves here, one good child is enough
+    or_node: bool,
+}
+
+/// Solves the game instead of scoring it with a depth limit: a position is only a win once every answer is refuted.
+/// Grows the tree towards the leaves that are the cheapest to prove or disprove.
+/// Plain proof-number search: the whole tree stays in memory, which grows with every node
+/// up to [`ProofNumberParams::max_nodes`]. See https://www.chessprogramming.org/Proof-Number_Search
+// TODO df-pn with a transposition table would need much less memory
+pub struct ProofNumberSearch<G: MinimaxDriver> {
+    params: ProofNumberParams,
+    // every node expanded so far, `children` holds indexes into it. Index 0 is the root
+    nodes: Vec<ProofNode<G::Move>>,
+    nodes_examined: u128,
+}
+
+impl<G: MinimaxDriver<Move = M>, M: GameMove> ProofNumberSearch<G> {
+    pub fn new(params: ProofNumberParams) -> Self {
+        Self {
+            params,
+            nodes: vec![],
+            nodes_examined: 0,
+        }
+    }
+
+    pub fn solve(&mut self, game: &G) -> ProofResult<M> {
+        self.nodes_examined = 0;
+        let player = game.get_current_player();
+        if let Some(outcome) = outcome(game, player) {
+            return ProofResult {
+                outcome,
+                best_move: None,
+                nodes_examined: 1,
+            };
+        }
+        // draws are proven by disproving the win and proving the draw separately
+        let (outcome, best_move) = match self.prove(game, Target::Win) {
+            Some(true) => (ProofOutcome::Win, self.proving_move()),
+            None => (ProofOutcome::Unknown, self.proving_move()),
+            Some(false) => match self.prove(game, Target::DrawOrBetter) {
+                Some(true) => (ProofOutcome::Draw, self.proving_move()),
+                None => (ProofOutcome::Unknown, self.proving_move()),
+                Some(false) => (ProofOutcome::Loss, game.get_possible_moves().next()),
+            },
+        };
+        ProofResult {
+            outcome,
+            best_move,
+            nodes_examined: self.nodes_examined,
+        }
+    }
+
+    /// Proven, disproven or out of nodes
+    fn prove(&mut self, game: &G, target: Target) -> Option<bool> {
+        let mut game = game.clone();
+        let root_player = game.get_current_player();
+        self.nodes.clear();
+        self.add_node(None, true, (1, 1));
+        let mut path = vec![];
+        loop {
+            let root = &self.nodes[0];
+            if root.proof == 0 || root.disproof == 0 {
+                return Some(root.proof == 0);
+            }
+            if matches!(self.params.max_nodes, Some(max) if self.nodes_examined >= max) {
+                return None;
+            }
+            // most proving node: the cheapest child to prove below the root player, to disprove below the opponent
+            let mut current = 0;
+            path.clear();
+            path.push(current);
+            while !self.nodes[current].children.is_empty() {
+                let node = &self.nodes[current];
+                let number = |&&child: &&usize| match node.or_node {
+                    true => self.nodes[child].proof,
+                    false => self.nodes[child].disproof,
+                };
+                current = *node.children.iter().min_by_key(number).unwrap();
+                game.make_move(self.nodes[current].last_move.unwrap());
+                path.push(current);
+            }
+            self.expand(&mut game, current, root_player, target);
+            for &idx in path.iter().rev() {
+                self.update(idx);
+                if let Some(last_move) = self.nodes[idx].last_move {
+                    game.unmake_move(last_move);
+                }
+            }
+        }
+    }
+
+    /// Adds all the children of the leaf, the ones that end the game are already proven or disproven
+    fn expand(&mut self, game: &mut G, leaf: usize, root_player: Player, target: Target) {
+        let moves: Vec<M> = game.get_possible_moves().collect();
+        for next_move in moves {
+            game.make_move(next_move);
+            let numbers = match outcome(game, root_player) {
+                Some(ProofOutcome::Win) => (0, INFINITY),
+                Some(ProofOutcome::Draw) if target == Target::DrawOrBetter => (0, INFINITY),
+                Some(_) => (INFINITY, 0),
+                None => (1, 1),
+            };
+            let or_node = game.get_current_player() == root_player;
+            game.unmake_move(next_move);
+            let child = self.add_node(Some(next_move), or_node, numbers);
+            self.nodes[leaf].children.push(child);
+        }
+    }
+
+    fn update(&mut self, idx: usize) {
+        let node = &self.nodes[idx];
+        if node.children.is_empty() {
+            return;
+        }
+        let children = node.children.iter().map(|&child| &self.nodes[child]);
+        let sum = |numbers: &mut dyn Iterator<Item = ProofNumber>| {
+            numbers.fold(0, ProofNumber::saturating_add)
+        };
+        let (proof, disproof) = if node.or_node {
+            (
+                children.clone().map(|child| child.proof).min().unwrap(),
+                sum(&mut children.map(|child| child.disproof)),
+            )
+        } else {
+            (
+                sum(&mut children.clone().map(|child| child.proof)),
+                children.map(|child| child.disproof).min().unwrap(),
+            )
+        };
+        let node = &mut self.nodes[idx];
+        node.proof = proof;
+        node.disproof = disproof;
+    }
+
+    fn add_node(
+        &mut self,
+        last_move: Option<M>,
+        or_node: bool,
+        (proof, disproof): (ProofNumber, ProofNumber),
+    ) -> usize {
+        self.nodes.push(ProofNode {
+            last_move,
+            children: vec![],
+            proof,
+            disproof,
+            or_node,
+        });
+        self.nodes_examined += 1;
+        self.nodes.len() - 1
+    }
+
+    /// Child of the root closest to being proven, the proven one if there is one
+    fn proving_move(&self) -> Option<M> {
+        self.nodes[0]
+            .children
+            .iter()
+            .map(|&child| &self.nodes[child])
+            .min_by_key(|child| child.proof)
+            .and_then(|child| child.last_move)
+    }
+}
+
+/// Outcome for the player if the game is over
+fn outcome<G: MinimaxDriver>(game: &G, player: Player) -> Option<ProofOutcome> {
+    let evaluation = game.evaluate_score();
+    if evaluation.is_terminal {
+        return Some(
+            match (evaluation.score * player.score_multiplier()).signum() {
+                1 => ProofOutcome::Win,
+                -1 => ProofOutcome::Loss,
+                _ => ProofOutcome::Draw,
+            },
+        );
+    }
+    game.get_possible_moves()
+        .next()
+        .is_none()
+        .then_some(ProofOutcome::Draw)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        connect4::{puzzles::*, Connect4Game},
+        tictactoe::TicTacToeGame,
+    };
+    use rstest::*;
+
+    fn solve<G: MinimaxDriver>(game: &G) -> ProofResult<G::Move> {
+        ProofNumberSearch::new(ProofNumberParams::default()).solve(game)
+    }
+
+    #[rstest]
+    #[case::win_in_two(WIN_IN_TWO)]
+    #[case::win_in_three(WIN_IN_THREE)]
+    #[case::win_in_four(WIN_IN_FOUR)]
+    #[case::win_in_five(WIN_IN_FIVE)]
+    fn test_connect4_win_in_x(#[case] state: &str) {
+        let game = Connect4Game::from_state(state, None, Player::X);
+        let result = solve(&game);
+        assert_eq!(result.outcome, ProofOutcome::Win);
+        // the proving move keeps the win for the opponent's turn
+        let next = game.apply_move(result.best_move.unwrap());
+        assert_eq!(solve(&next).outcome, ProofOutcome::Loss);
+    }
+
+    #[test]
+    fn test_tictactoe_draw() {
+        let result = solve(&TicTacToeGame::default());
+        assert_eq!(result.outcome, ProofOutcome::Draw);
+        assert!(result.best_move.is_some());
+    }
+
+    #[rstest]
+    // win on the spot
+    #[case(
+        "
+        XX.
+        OO.
+        X..",
+        Player::O,
+        ProofOutcome::Win
+    )]
+    // two open threats, the block doesn't matter
+    #[case(
+        "
+        XX.
+        .XO
+        O..",
+        Player::O,
+        ProofOutcome::Loss
+    )]
+    fn test_tictactoe(
+        #[case] board_str: &str,
+        #[case] player: Player,
+        #[case] expected: ProofOutcome,
+    ) {
+        let game = TicTacToeGame::from_state(board_str, player);
+        assert_eq!(solve(&game).outcome, expected);
+    }
+
+    #[test]
+    fn test_ended_game() {
+        let game = TicTacToeGame::from_state(
+            "
+            XXX
+            OO.
+            ...",
+            Player::O,
+        );
+        let result = solve(&game);
+        assert_eq!(result.outcome, ProofOutcome::Loss);
+        assert_eq!(result.best_move, None);
+    }
+
+    #[test]
+    fn test_node_limit() {
+        let mut search = ProofNumberSearch::new(ProofNumberParams {
+            max_nodes: Some(100),
+        });
+        let result = search.solve(&Connect4Game::default());
+        assert_eq!(result.outcome, ProofOutcome::Unknown);
+        assert!(result.best_move.is_some());
+        assert!(result.nodes_examined < 200);
+    }
+}
@@ -0,0 +1,307 @@
+use std::fmt::Debug;
+
+use crate::multiplayer::*;
+
+const WIDTH: usize = 7;
+const HEIGHT: usize = 6;
+const PLAYERS: usize = 3;
+/// Scores of all the players add up to about this, the winner takes all of it
+pub const SCORE_SUM: i32 = 1000;
+
+/// Index of the column to drop the piece in
+pub type Move = usize;
+
+/// Connect four with three players taking turns: A, B and C
+#[derive(Clone)]
+pub struct ThreePlayerConnectGame {
+    // only changed together with the board, by the moves
+    current_player: PlayerIndex,
+    /// Rows from the top
+    board: [[Option<PlayerIndex>; WIDTH]; HEIGHT],
+}
+
+impl ThreePlayerConnectGame {
+    /// Does not validate if the state is correct or reachable
+    pub fn from_state(board_str: &str, current_player: PlayerIndex) -> Self {
+        let mut game = Self {
+            current_player,
+            ..Default::default()
+        };
+        let board_chars = board_str.chars().filter(|c| !c.is_whitespace());
+        itertools::iproduct!(0..HEIGHT, 0..WIDTH)
+            .zip(board_chars)
+            .for_each(|((i, j), c)| game.board[i][j] = player_from_char(c));
+        game
+    }
+
+    /// Owner of the piece in the cell, row 0 is the top of the board
+    pub fn cell(&self, row: usize, column: usize) -> Option<PlayerIndex> {
+        self.board[row][column]
+    }
+
+    /// Player with four in a row, if any
+    pub fn get_winner(&self) -> Option<PlayerIndex> {
+        windows().find_map(|window| match self.window_count(&window) {
+            Some((player, 4)) => Some(player),
+            _ => None,
+        })
+    }
+
+    pub fn has_ended(&self) -> bool {
+        self.get_winner().is_some() || self.get_possible_moves().next().is_none()
+    }
+
+    /// The only player with pieces in the window and how many they are. None if empty or shared
+    fn window_count(&self, window: &[(usize, usize); 4]) -> Option<(PlayerIndex, usize)> {
+        let mut owner = None;
+        let mut count = 0;
+        for &(i, j) in window {
+            match (self.board[i][j], owner) {
+                (None, _) => {}
+                (Some(player), None) => {
+                    owner = Some(player);
+                    count = 1;
+                }
+                (Some(player), Some(current)) if player == current => count += 1,
+                _ => return None,
+            }
+        }
+        owner.map(|player| (player, count))
+    }
+}
+
+/// Every line of four cells on the board
+fn windows() -> impl Iterator<Item = [(usize, usize); 4]> {
+    const DIRECTIONS: [(isize, isize); 4] = [(0, 1), (1, 1), (1, 0), (-1, 1)];
+    itertools::iproduct!(0..HEIGHT, 0..WIDTH, DIRECTIONS).filter_map(|(i, j, (di, dj))| {
+        let cell = |k: isize| {
+            let (row, col) = (i as isize + k * di, j as isize + k * dj);
+            ((0..HEIGHT as isize).contains(&row) && (0..WIDTH as isize).contains(&col))
+                .then_some((row as usize, col as usize))
+        };
+        Some([cell(0)?, cell(1)?, cell(2)?, cell(3)?])
+    })
+}
+
+fn player_from_char(c: char) -> Option<PlayerIndex> {
+    match c {
+        'A' | 'a' => Some(0),
+        'B' | 'b' => Some(1),
+        'C' | 'c' => Some(2),
+        '.' => None,
+        _ => panic!(),
+    }
+}
+
+impl MultiplayerDriver for ThreePlayerConnectGame {
+    type Move = Move;
+
+    fn player_count(&self) -> usize {
+        PLAYERS
+    }
+
+    fn current_player(&self) -> PlayerIndex {
+        self.current_player
+    }
+
+    /// Share of [`SCORE_SUM`] by the lines each player can still complete, longer ones worth more
+    fn evaluate_scores(&self) -> MultiEvaluation {
+        let mut potential = [1; PLAYERS];
+        for window in windows() {
+            match self.window_count(&window) {
+                Some((player, 4)) => {
+                    let mut scores = vec![0; PLAYERS];
+                    scores[player] = SCORE_SUM;
+                    return MultiEvaluation {
+                        scores,
+                        is_terminal: true,
+                    };
+                }
+                Some((player, count)) => potential[player] += (count * count) as i32,
+                None => {}
+            }
+        }
+        let total: i32 = potential.iter().sum();
+        MultiEvaluation {
+            scores: potential.iter().map(|p| p * SCORE_SUM / total).collect(),
+            is_terminal: self.get_possible_moves().next().is_none(),
+        }
+    }
+
+    fn get_possible_moves(&self) -> impl Iterator<Item = Move> + '_ {
+        (0..WIDTH).filter(|&j| self.board[0][j].is_none())
+    }
+
+    /// No checks are applied. Assumes that the move has been taken from [`MultiplayerDriver::get_possible_moves`]
+    fn make_move(&mut self, next_move: Move) {
+        let row = (0..HEIGHT)
+            .rev()
+            .find(|&i| self.board[i][next_move].is_none())
+            .unwrap();
+        self.board[row][next_move] = Some(self.current_player);
+        self.current_player = (self.current_player + 1) % PLAYERS;
+    }
+
+    fn unmake_move(&mut self, last_move: Move) {
+        let row = (0..HEIGHT)
+            .find(|&i| self.board[i][last_move].is_some())
+            .unwrap();
+        self.board[row][last_move] = None;
+        self.current_player = (self.current_player + PLAYERS - 1) % PLAYERS;
+    }
+}
+
+impl Debug for ThreePlayerConnectGame {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        const NAMES: [char; PLAYERS] = ['A', 'B', 'C'];
+        for row in &self.board {
+            for cell in row {
+                write!(f, "{} ", cell.map_or('.', |player| NAMES[player]))?;
+            }
+            writeln!(f)?;
+        }
+        write!(f, "nx: {}", NAMES[self.current_player])
+    }
+}
+
+impl Default for ThreePlayerConnectGame {
+    fn default() -> Self {
+        Self {
+            current_player: 0,
+            board: [[None; WIDTH]; HEIGHT],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::*;
+
+    #[test]
+    fn test_winner() {
+        let game = ThreePlayerConnectGame::from_state(
+            "
+            .......
+            .......
+            ...B...
+            ..BC...
+            .BCC...
+            BAAAC..",
+            0,
+        );
+        assert_eq!(game.get_winner(), Some(1));
+        assert!(game.has_ended());
+        assert_eq!(game.evaluate_scores().scores, vec![0, SCORE_SUM, 0]);
+    }
+
+    #[test]
+    fn test_make_unmake_move() {
+        let mut game = ThreePlayerConnectGame::default();
+        for column in [3, 3, 4, 2] {
+            game.make_move(column);
+        }
+        assert_eq!(game.current_player(), 1);
+        assert_eq!(game.cell(4, 3), Some(1));
+        for column in [2, 4, 3, 3] {
+            game.unmake_move(column);
+        }
+        assert_eq!(game.current_player(), 0);
+        assert!(game.board.iter().flatten().all(|cell| cell.is_none()));
+    }
+
+    #[test]
+    fn test_scores_share_the_sum() {
+        let game = ThreePlayerConnectGame::from_state(
+            "
+            .......
+            .......
+            .......
+            .......
+            .......
+            .AA..BC",
+            1,
+        );
+        let evaluation = game.evaluate_scores();
+        assert!(!evaluation.is_terminal);
+        assert!(evaluation.scores.iter().sum::<i32>() <= SCORE_SUM);
+        // two in a row with room to grow is worth the most
+        assert!(evaluation.scores[0] > evaluation.scores[1]);
+    }
+
+    #[rstest]
+    // A wins on the spot
+    #[case(
+        "
+        .......
+        .......
+        .......
+        .......
+        C..C.B.
+        CAAA.BB",
+        0,
+        4
+    )]
+    // B would win next, A blocks
+    #[case(
+        "
+        .......
+        .......
+        .......
+        .B.....
+        AB.C...
+        ABCAC..",
+        0,
+        1
+    )]
+    fn test_search(
+        #[case] board_str: &str,
+        #[case] player: PlayerIndex,
+        #[case] expected: Move,
+        #[values(MultiplayerAlgorithm::MaxN, MultiplayerAlgorithm::Paranoid)]
+        algorithm: MultiplayerAlgorithm,
+        #[values(None, Some(SCORE_SUM))] max_score_sum: Option<i32>,
+    ) {
+        let game = ThreePlayerConnectGame::from_state(board_str, player);
+        let result = MultiplayerSearch::new(MultiplayerParams {
+            max_depth: 3,
+            algorithm,
+            max_score_sum,
+            ..Default::default()
+        })
+        .search(&game);
+        assert_eq!(result.best_move, Some(expected));
+        assert_eq!(result.principal_variation[0], expected);
+        assert_eq!(result.scores.len(), PLAYERS);
+    }
+
+    #[rstest]
+    fn test_pruning_keeps_the_result(
+        #[values(MultiplayerAlgorithm::MaxN, MultiplayerAlgorithm::Paranoid)]
+        algorithm: MultiplayerAlgorithm,
+    ) {
+        let game = ThreePlayerConnectGame::from_state(
+            "
+            .......
+            .......
+            .......
+            .......
+            ...C...
+            ..ABA..",
+            1,
+        );
+        let search = |pruning_enabled| {
+            MultiplayerSearch::new(MultiplayerParams {
+                max_depth: 4,
+                algorithm,
+                max_score_sum: Some(SCORE_SUM),
+                pruning_enabled,
+            })
+            .search(&game)
+        };
+        let (full, pruned) = (search(false), search(true));
+        assert_eq!(pruned.best_move, full.best_move);
+        assert_eq!(pruned.scores, full.scores);
+        assert!(pruned.nodes_examined < full.nodes_examined);
+    }
+}
@@ -1,24 +1,42 @@
 use itertools::iproduct;
-use std::fmt::Debug;
+use std::{fmt::Debug, sync::OnceLock};
 
-use crate::{game::*, minimax::*};
+use crate::{game::*, minimax::*, zobrist::ZobristTable};
+
+/// Row and column of the cell to mark
+pub type Move = (usize, usize);
 
 #[derive(Clone)]
 pub struct TicTacToeGame {
-    pub current_player: Player,
-    pub board: BoardType,
+    current_player: Player,
+    board: BoardType,
+    /// Zobrist hash of the board and the player to move, kept in sync by the moves
+    hash: GameHash,
+}
+
+fn zobrist() -> &'static ZobristTable<GameHash> {
+    static KEYS: OnceLock<ZobristTable<GameHash>> = OnceLock::new();
+    KEYS.get_or_init(|| ZobristTable::new(9, 2, 3))
 }
 
 impl TicTacToeGame {
     /// Does not validate if the state is correct or reachable (ie might have board filled with X)
     pub fn from_state(board_str: &str, current_player: Player) -> Self {
-        let mut game = TicTacToeGame::default();
-        game.current_player = current_player;
+        let mut game = TicTacToeGame {
+            current_player,
+            ..Default::default()
+        };
         let board_chars = board_str.chars().filter(|c| !c.is_whitespace());
         iproduct!(0..3, 0..3)
             .zip(board_chars)
             .for_each(|((i, j), c)| game.board.set(i, j, Player::from(c)));
-        return game;
+        game.hash = zobrist().hash_board(&game.board, current_player);
+        game
+    }
+
+    /// Mark in the cell, [`Player::None`] if it's still empty
+    pub fn cell(&self, row: usize, column: usize) -> Player {
+        self.board.get(row, column)
     }
 
     fn _score(&self, i: usize, j: usize) -> i32 {
@@ -52,11 +70,15 @@ impl Default for TicTacToeGame {
         Self {
             current_player: Player::X,
             board: [Player::None; 9],
+            hash: 0,
         }
     }
 }
 
 impl MinimaxDriver for TicTacToeGame {
+    type Move = Move;
+    type Hash = GameHash;
+
     fn get_winner(&self) -> Player {
         for pos in win_positions_to_check() {
             let score: i32 = pos.map(|(i, j)| self._score(i, j)).sum();
@@ -66,24 +88,34 @@ impl MinimaxDriver for TicTacToeGame {
                 return Player::O;
             }
         }
-        return Player::None;
+        Player::None
     }
 
-    fn get_possible_moves(&self) -> Box<dyn Iterator<Item = Move> + '_> {
-        Box::new(iproduct!(0..3, 0..3).filter(|(i, j)| self.board.get(*i, *j) == Player::None))
+    fn get_possible_moves(&self) -> impl Iterator<Item = Move> + '_ {
+        iproduct!(0..3, 0..3).filter(|(i, j)| self.board.get(*i, *j) == Player::None)
     }
 
-    fn apply_move(&self, next_move: Move) -> Box<dyn MinimaxDriver> {
-        let mut new_game = Box::new(self.clone());
-        new_game
-            .board
+    fn make_move(&mut self, next_move: Move) {
+        self.board
             .set(next_move.0, next_move.1, self.current_player);
+        self.hash ^= zobrist().player(next_move.0 * 3 + next_move.1, self.current_player)
+            ^ zobrist().side_to_move();
+        self.current_player = self.current_player.next();
+    }
 
-        new_game.current_player = self.current_player.next();
-        return new_game;
+    fn unmake_move(&mut self, last_move: Move) {
+        self.current_player = self.current_player.next();
+        self.board.set(last_move.0, last_move.1, Player::None);
+        self.hash ^= zobrist().player(last_move.0 * 3 + last_move.1, self.current_player)
+            ^ zobrist().side_to_move();
     }
 
     fn get_hash(&self) -> GameHash {
+        self.hash
+    }
+
+    /// Exact encoding of the board, no collisions possible
+    fn get_verification_hash(&self) -> Option<GameHash> {
         let mut hash: u128 = self
             .board
             .iter()
@@ -91,7 +123,30 @@ impl MinimaxDriver for TicTacToeGame {
             .map(|(val, pos)| (*val as u128) * 4u128.pow(pos))
             .sum();
         hash += self.current_player as u128;
-        hash
+        Some(hash)
+    }
+
+    /// Center first, then corners, then edges
+    fn move_order_hint(&self, next_move: Move) -> Option<Score> {
+        Some(match next_move {
+            (1, 1) => 2,
+            (i, j) if i != 1 && j != 1 => 1,
+            _ => 0,
+        })
+    }
+
+    /// An extra piece never hurts in tic tac toe, so passing can't be better than the best move
+    fn null_move_allowed(&self) -> bool {
+        !self.has_ended()
+    }
+
+    fn make_null_move(&mut self) {
+        self.current_player = self.current_player.next();
+        self.hash ^= zobrist().side_to_move();
+    }
+
+    fn unmake_null_move(&mut self) {
+        self.make_null_move()
     }
 
     fn get_current_player(&self) -> Player {
@@ -148,6 +203,7 @@ fn win_positions_to_check() -> impl Iterator<Item = impl Iterator<Item = (usize,
 mod tests {
     use super::*;
     use rstest::*;
+    use std::time::Duration;
 
     #[rstest]
     #[case(
@@ -201,49 +257,61 @@ mod tests {
         O.O";
         let game = TicTacToeGame::from_state(state, Player::X);
         let mut minimax = Minimax::new(MinimaxParams::default());
-        let node = minimax.minimax(&game);
-        assert!([Some((0, 1)), Some((1, 1))].contains(&node.get_best_move()));
+        let result = minimax.minimax(&game);
+        assert!([Some((0, 1)), Some((1, 1))].contains(&result.best_move));
 
         let game = TicTacToeGame::from_state(state, Player::O);
         let mut minimax = Minimax::new(MinimaxParams::default());
-        let node = minimax.minimax(&game);
-        assert_eq!(Some((2, 1)), node.get_best_move());
+        let result = minimax.minimax(&game);
+        assert_eq!(Some((2, 1)), result.best_move);
     }
 
-    fn play(game: TicTacToeGame) -> (Box<dyn MinimaxDriver>, i32) {
-        let mut moves = 0;
+    #[test]
+    fn test_pruned_moves_dont_replace_best_move() {
+        // only the center holds the draw. Pruned replies score the same as the best one
+        // without being as good, so they must not win the ties
+        let state = "
+        X..
+        ...
+        ...";
+        let game = TicTacToeGame::from_state(state, Player::O);
         let mut minimax = Minimax::new(MinimaxParams::default());
-        let mut decision_node = minimax.minimax(&game);
-        let mut new_game = Box::new(game) as Box<dyn MinimaxDriver>;
-        while decision_node.best_move.is_some() {
-            let next_move = decision_node.best_move.unwrap();
-            new_game = new_game.apply_move(next_move);
-            decision_node = decision_node.moves.get(&next_move).unwrap().clone();
-            moves += 1;
-        }
-        return (new_game, moves);
+        let result = minimax.minimax(&game);
+        assert_eq!(Some((1, 1)), result.best_move);
+    }
+
+    fn play(game: TicTacToeGame, cache_enabled: bool) -> (TicTacToeGame, usize) {
+        let mut minimax = Minimax::new(MinimaxParams {
+            cache_enabled,
+            ..Default::default()
+        });
+        let result = minimax.minimax(&game);
+        let line = &result.principal_variation;
+        let moves = line.len();
+        let final_game = apply_moves(&game, line).pop().unwrap_or(game);
+        (final_game, moves)
     }
 
     #[rstest]
-    fn test_winning_moves_two_turns() {
+    fn test_winning_moves_two_turns(#[values(false, true)] cache_enabled: bool) {
         let state = "
         ...
         OXX
         ..O";
         let game = TicTacToeGame::from_state(state, Player::O);
-        let (final_game, moves) = play(game);
+        let (final_game, moves) = play(game, cache_enabled);
         assert_eq!(moves, 3);
         assert_eq!(final_game.get_winner(), Player::O);
     }
 
     #[rstest]
-    fn test_winning_moves_three_turns() {
+    fn test_winning_moves_three_turns(#[values(false, true)] cache_enabled: bool) {
         let state = "
         X..
         .O.
         O.X";
         let game = TicTacToeGame::from_state(state, Player::X);
-        let (final_game, moves) = play(game);
+        let (final_game, moves) = play(game, cache_enabled);
         assert_eq!(moves, 3);
         assert_eq!(final_game.get_winner(), Player::X);
 
@@ -252,30 +320,30 @@ mod tests {
         .O.
         ..X";
         let game = TicTacToeGame::from_state(state, Player::X);
-        let (final_game, moves) = play(game);
+        let (final_game, moves) = play(game, cache_enabled);
         assert_eq!(moves, 3);
         assert_eq!(final_game.get_winner(), Player::X);
     }
 
     #[rstest]
-    fn test_doesnt_make_noob_mistake() {
+    fn test_doesnt_make_noob_mistake(#[values(false, true)] cache_enabled: bool) {
         let state = "
         X..
         .O.
         ..X";
         let game = TicTacToeGame::from_state(state, Player::O);
-        let (final_game, _moves) = play(game);
+        let (final_game, _moves) = play(game, cache_enabled);
         assert_eq!(final_game.get_winner(), Player::None);
     }
 
     #[rstest]
-    fn test_punishes_noob_openings() {
+    fn test_punishes_noob_openings(#[values(false, true)] cache_enabled: bool) {
         let state = "
         XO.
         ...
         ...";
         let game = TicTacToeGame::from_state(state, Player::X);
-        let (final_game, moves) = play(game);
+        let (final_game, moves) = play(game, cache_enabled);
         assert_eq!(moves, 5);
         assert_eq!(final_game.get_winner(), Player::X);
 
@@ -284,53 +352,186 @@ mod tests {
         ...
         O..";
         let game = TicTacToeGame::from_state(state, Player::X);
-        let (final_game, moves) = play(game);
+        let (final_game, moves) = play(game, cache_enabled);
         assert_eq!(moves, 5);
         assert_eq!(final_game.get_winner(), Player::X);
     }
 
     #[rstest]
-    fn test_fastest_win() {
+    fn test_fastest_win(#[values(false, true)] cache_enabled: bool) {
         let state = "
         OOX
         O.X
         X..";
         let game = TicTacToeGame::from_state(state, Player::X);
-        let (final_game, moves) = play(game);
+        let (final_game, moves) = play(game, cache_enabled);
         assert_eq!(moves, 1);
         assert_eq!(final_game.get_winner(), Player::X);
     }
 
-    #[test]
-    fn test_best_moves_always_end_in_draw() {
+    #[rstest]
+    fn test_best_moves_always_end_in_draw(#[values(false, true)] cache_enabled: bool) {
         let state = "
         ...
         ...
         ...";
         let game = TicTacToeGame::from_state(state, Player::X);
-        let (final_game, _moves) = play(game);
+        let (final_game, _moves) = play(game, cache_enabled);
         assert_eq!(final_game.get_winner(), Player::None);
     }
 
+    #[rstest]
+    fn test_build_tree(#[values(false, true)] build_tree: bool) {
+        let state = "
+        X..
+        .O.
+        ..X";
+        let game = TicTacToeGame::from_state(state, Player::O);
+        let mut minimax = Minimax::new(MinimaxParams {
+            build_tree,
+            ..Default::default()
+        });
+        let result = minimax.minimax(&game);
+        assert_eq!(result.tree.is_some(), build_tree);
+        if let Some(tree) = result.tree {
+            assert_eq!(tree.principal_variation(), result.principal_variation);
+            assert_eq!(tree.score, result.score);
+        }
+        assert_eq!(
+            minimax.get_principal_variation(),
+            result.principal_variation
+        );
+    }
+
     #[test]
-    fn test_hash() {
+    fn test_make_unmake_move() {
+        let state = "
+        X..
+        .O.
+        ...";
+        let mut game = TicTacToeGame::from_state(state, Player::X);
+        let original_hash = game.get_hash();
+        game.make_move((2, 2));
+        assert_eq!(game.get_current_player(), Player::O);
+        assert_eq!(
+            game.get_hash(),
+            TicTacToeGame::from_state(state, Player::X)
+                .apply_move((2, 2))
+                .get_hash()
+        );
+        game.unmake_move((2, 2));
+        assert_eq!(game.get_hash(), original_hash);
+    }
+
+    #[rstest]
+    fn test_parallel_search(#[values(false, true)] time_budget: bool) {
+        let state = "
+        X..
+        .O.
+        ..X";
+        let game = TicTacToeGame::from_state(state, Player::O);
+        let mut minimax = Minimax::new(MinimaxParams {
+            threads: 3,
+            time_budget: time_budget.then(|| Duration::from_secs(60)),
+            ..Default::default()
+        });
+        let result = minimax.minimax_parallel(&game);
+        assert!(!result.interrupted);
+        assert_eq!(result.score, 0);
+        // playing a corner loses
+        assert!(
+            [Some((0, 1)), Some((1, 0)), Some((1, 2)), Some((2, 1))].contains(&result.best_move)
+        );
+        assert_eq!(
+            minimax.get_principal_variation(),
+            result.principal_variation
+        );
+    }
+
+    #[test]
+    fn test_null_move() {
+        let state = "
+        X..
+        .O.
+        ...";
+        let mut game = TicTacToeGame::from_state(state, Player::X);
+        assert!(game.null_move_allowed());
+        game.make_null_move();
+        assert_eq!(game.get_current_player(), Player::O);
+        assert_eq!(
+            game.get_hash(),
+            TicTacToeGame::from_state(state, Player::O).get_hash()
+        );
+        game.unmake_null_move();
+        assert_eq!(
+            game.get_hash(),
+            TicTacToeGame::from_state(state, Player::X).get_hash()
+        );
+    }
+
+    #[rstest]
+    fn test_null_move_pruning(#[values(false, true)] cache_enabled: bool) {
+        let state = "
+        ...
+        ...
+        ...";
+        let game = TicTacToeGame::from_state(state, Player::X);
+        let expected = Minimax::new(MinimaxParams {
+            cache_enabled,
+            ..Default::default()
+        })
+        .minimax(&game);
+        let mut minimax = Minimax::new(MinimaxParams {
+            cache_enabled,
+            null_move_reduction: Some(2),
+            ..Default::default()
+        });
+        let result = minimax.minimax(&game);
+        // no zugzwang, so passing never changes the result
+        assert_eq!(result.score, expected.score);
+        assert!(minimax.get_selective_stats().null_move_cutoffs > 0);
+    }
+
+    #[test]
+    fn test_verification_hash() {
         let state = "
         ...
         ...
         ...";
         let mut game = TicTacToeGame::from_state(state, Player::X);
-        assert_eq!(game.get_hash(), 1);
+        assert_eq!(game.get_verification_hash(), Some(1));
         game.current_player = Player::O;
-        assert_eq!(game.get_hash(), 2);
+        assert_eq!(game.get_verification_hash(), Some(2));
 
         let state = "
         X..
         ...
         ...";
         let mut game = TicTacToeGame::from_state(state, Player::X);
-        assert_eq!(game.get_hash(), 5);
+        assert_eq!(game.get_verification_hash(), Some(5));
         game.current_player = Player::O;
-        assert_eq!(game.get_hash(), 6);
+        assert_eq!(game.get_verification_hash(), Some(6));
+    }
+
+    #[test]
+    fn test_hash() {
+        let empty = TicTacToeGame::default();
+        assert_eq!(empty.get_hash(), 0);
+        // same position reached in a different order
+        let a = apply_moves(&empty, &[(0, 0), (1, 1), (2, 2)])
+            .pop()
+            .unwrap();
+        let b = apply_moves(&empty, &[(2, 2), (1, 1), (0, 0)])
+            .pop()
+            .unwrap();
+        assert_eq!(a.get_hash(), b.get_hash());
+        let state = "
+        X..
+        .O.
+        ..X";
+        let expected = TicTacToeGame::from_state(state, Player::O).get_hash();
+        assert_eq!(a.get_hash(), expected);
+        assert_ne!(a.get_hash(), empty.apply_move((0, 0)).get_hash());
     }
 
     #[fixture]
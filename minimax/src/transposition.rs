@@ -0,0 +1,416 @@
+use std::{collections::hash_map::DefaultHasher, hash::Hasher, ops::Range};
+
+use crate::minimax::*;
+
+/// What the stored score says about the real value of the node
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum Bound {
+    /// Score was inside the search window, so it's the real value
+    Exact,
+    /// Search failed high, real value is at least the score
+    Lower,
+    /// Search failed low, real value is at most the score
+    Upper,
+}
+
+impl Bound {
+    /// Classifies a score against the window it was searched with
+    pub fn from_window(score: Score, alfa: Score, beta: Score) -> Self {
+        if score <= alfa {
+            Bound::Upper
+        } else if score >= beta {
+            Bound::Lower
+        } else {
+            Bound::Exact
+        }
+    }
+}
+
+pub struct TranspositionEntry<M, H = GameHash> {
+    pub score: Score,
+    pub best_move: Option<M>,
+    /// How many plies were searched below the node
+    pub depth: u32,
+    pub bound: Bound,
+    /// Decision tree below the node, if it's being built
+    pub node: Option<NodeType<M>>,
+    /// See [`MinimaxDriver::get_verification_hash`]
+    pub verification: Option<H>,
+}
+
+impl<M: GameMove, H> TranspositionEntry<M, H> {
+    /// True if the entry can replace a search of `depth` plies with the given window
+    pub fn is_usable(&self, depth: u32, alfa: Score, beta: Score) -> bool {
+        if self.depth < depth {
+            return false;
+        }
+        match self.bound {
+            Bound::Exact => true,
+            Bound::Lower => self.score >= beta,
+            Bound::Upper => self.score <= alfa,
+        }
+    }
+}
+
+/// What happens when a new entry lands on a slot that is already taken
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum ReplacementStrategy {
+    /// The newest entry always wins
+    AlwaysReplace,
+    /// Keeps the deeper search, unless it's left over from an older search
+    DepthPreferred,
+    /// Buckets of two slots: one depth preferred, the other always replaced
+    TwoTier,
+}
+
+/// Counters since the table was created
+#[derive(Clone, Copy, Default, Debug)]
+pub struct CacheStats {
+    pub hits: u128,
+    pub misses: u128,
+    /// Entries of other positions thrown out to make room
+    pub overwrites: u128,
+    /// Slots in use
+    pub entries: usize,
+    pub capacity: usize,
+}
+
+struct Slot<M, H> {
+    hash: H,
+    /// Search that wrote the entry
+    generation: u8,
+    entry: TranspositionEntry<M, H>,
+}
+
+/// Fixed size hash table, see https://www.chessprogramming.org/Transposition_Table
+pub struct TranspositionTable<M, H = GameHash> {
+    slots: Vec<Option<Slot<M, H>>>,
+    capacity: usize,
+    strategy: ReplacementStrategy,
+    generation: u8,
+    stats: CacheStats,
+}
+
+// derive(Default) would require M: Default
+impl<M, H> Default for TranspositionTable<M, H> {
+    fn default() -> Self {
+        Self::new(16, ReplacementStrategy::DepthPreferred)
+    }
+}
+
+impl<M, H> TranspositionTable<M, H> {
+    /// Table taking up about `size_mb` megabytes
+    pub fn new(size_mb: usize, strategy: ReplacementStrategy) -> Self {
+        let slot_size = std::mem::size_of::<Option<Slot<M, H>>>();
+        Self::with_capacity(size_mb * 1024 * 1024 / slot_size, strategy)
+    }
+
+    /// Table with room for `capacity` entries. Memory is only taken on the first insert
+    pub fn with_capacity(capacity: usize, strategy: ReplacementStrategy) -> Self {
+        Self {
+            slots: vec![],
+            capacity: capacity.max(2),
+            strategy,
+            generation: 0,
+            stats: CacheStats::default(),
+        }
+    }
+}
+
+impl<M: GameMove, H: HashKey> TranspositionTable<M, H> {
+    /// Ages the entries already in the table, they make room for the ones of the new search
+    pub fn new_search(&mut self) {
+        self.generation = self.generation.wrapping_add(1);
+    }
+
+    pub fn get(&mut self, hash: H) -> Option<&TranspositionEntry<M, H>> {
+        match self.find(hash) {
+            Some(i) => {
+                self.stats.hits += 1;
+                self.slots[i].as_ref().map(|slot| &slot.entry)
+            }
+            None => {
+                self.stats.misses += 1;
+                None
+            }
+        }
+    }
+
+    /// Same as [`TranspositionTable::get`], without counting as a lookup
+    pub fn peek(&self, hash: H) -> Option<&TranspositionEntry<M, H>> {
+        self.find(hash)
+            .and_then(|i| self.slots[i].as_ref().map(|slot| &slot.entry))
+    }
+
+    pub fn insert(&mut self, hash: H, entry: TranspositionEntry<M, H>) {
+        if self.slots.is_empty() {
+            self.slots.resize_with(self.capacity, || None);
+        }
+        let slot = Slot {
+            hash,
+            generation: self.generation,
+            entry,
+        };
+        let first = self.bucket(hash).start;
+        // entries of the same position are updated in place
+        if let Some(i) = self.find(hash) {
+            match self.strategy {
+                ReplacementStrategy::AlwaysReplace => self.store(i, slot),
+                ReplacementStrategy::DepthPreferred => {
+                    if self.should_replace(i, &slot.entry) {
+                        self.store(i, slot)
+                    }
+                }
+                ReplacementStrategy::TwoTier => {
+                    // updates refused by the depth preferred slot go to the always replace one
+                    if i == first + 1 || self.should_replace(i, &slot.entry) {
+                        self.store(i, slot)
+                    } else {
+                        self.store(first + 1, slot)
+                    }
+                }
+            }
+            return;
+        }
+        match self.strategy {
+            ReplacementStrategy::AlwaysReplace => self.store(first, slot),
+            ReplacementStrategy::DepthPreferred => {
+                if self.should_replace(first, &slot.entry) {
+                    self.store(first, slot)
+                }
+            }
+            ReplacementStrategy::TwoTier => {
+                if self.should_replace(first, &slot.entry) {
+                    // the old entry still gets a chance in the always replace slot
+                    if let Some(old) = self.take(first) {
+                        self.store(first + 1, old);
+                    }
+                    self.store(first, slot);
+                } else {
+                    self.store(first + 1, slot);
+                }
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.stats.entries
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stats.entries == 0
+    }
+
+    pub fn clear(&mut self) {
+        self.slots.clear();
+        self.stats.entries = 0;
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            capacity: self.capacity,
+            ..self.stats
+        }
+    }
+
+    fn find(&self, hash: H) -> Option<usize> {
+        self.bucket(hash)
+            .find(|&i| matches!(&self.slots[i], Some(slot) if slot.hash == hash))
+    }
+
+    /// Slots where the position can be stored. Empty before the first insert
+    fn bucket(&self, hash: H) -> Range<usize> {
+        if self.slots.is_empty() {
+            return 0..0;
+        }
+        let start = self.bucket_start(hash);
+        match self.strategy {
+            ReplacementStrategy::TwoTier => start..start + 2,
+            _ => start..start + 1,
+        }
+    }
+
+    fn bucket_start(&self, hash: H) -> usize {
+        let bucket_size = match self.strategy {
+            ReplacementStrategy::TwoTier => 2,
+            _ => 1,
+        };
+        let mut hasher = DefaultHasher::new();
+        hash.hash(&mut hasher);
+        (hasher.finish() as usize % (self.capacity / bucket_size)) * bucket_size
+    }
+
+    fn should_replace(&self, index: usize, entry: &TranspositionEntry<M, H>) -> bool {
+        match &self.slots[index] {
+            None => true,
+            Some(old) => old.generation != self.generation || entry.depth >= old.entry.depth,
+        }
+    }
+
+    fn store(&mut self, index: usize, slot: Slot<M, H>) {
+        let hash = slot.hash;
+        match self.slots[index].replace(slot) {
+            None => self.stats.entries += 1,
+            Some(old) if old.hash != hash => self.stats.overwrites += 1,
+            Some(_) => {}
+        }
+    }
+
+    fn take(&mut self, index: usize) -> Option<Slot<M, H>> {
+        let slot = self.slots[index].take();
+        if slot.is_some() {
+            self.stats.entries -= 1;
+        }
+        slot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    fn entry(score: Score, depth: u32, bound: Bound) -> TranspositionEntry<usize> {
+        TranspositionEntry {
+            score,
+            best_move: None,
+            depth,
+            bound,
+            node: None,
+            verification: None,
+        }
+    }
+
+    /// Different positions that have to share a bucket
+    fn colliding_hashes(table: &TranspositionTable<usize>, count: usize) -> Vec<GameHash> {
+        let start = table.bucket_start(0);
+        (0..)
+            .filter(|&hash| table.bucket_start(hash) == start)
+            .take(count)
+            .collect()
+    }
+
+    #[test]
+    fn test_bound_from_window() {
+        assert_eq!(Bound::from_window(-10, -10, 10), Bound::Upper);
+        assert_eq!(Bound::from_window(10, -10, 10), Bound::Lower);
+        assert_eq!(Bound::from_window(0, -10, 10), Bound::Exact);
+    }
+
+    #[test]
+    fn test_is_usable() {
+        // too shallow
+        assert!(!entry(5, 2, Bound::Exact).is_usable(3, -10, 10));
+        assert!(entry(5, 3, Bound::Exact).is_usable(3, -10, 10));
+        // lower bound only useful if it fails high
+        assert!(!entry(5, 3, Bound::Lower).is_usable(3, -10, 10));
+        assert!(entry(15, 3, Bound::Lower).is_usable(3, -10, 10));
+        // upper bound only useful if it fails low
+        assert!(!entry(5, 3, Bound::Upper).is_usable(3, -10, 10));
+        assert!(entry(-15, 3, Bound::Upper).is_usable(3, -10, 10));
+    }
+
+    #[test]
+    fn test_keeps_deepest_entry() {
+        let mut table = TranspositionTable::default();
+        table.insert(1, entry(5, 4, Bound::Exact));
+        table.insert(1, entry(7, 2, Bound::Exact));
+        assert_eq!(table.get(1).unwrap().score, 5);
+        table.insert(1, entry(9, 4, Bound::Lower));
+        assert_eq!(table.get(1).unwrap().score, 9);
+        assert_eq!(table.len(), 1);
+    }
+
+    #[rstest]
+    #[case(ReplacementStrategy::AlwaysReplace, 9)]
+    #[case(ReplacementStrategy::DepthPreferred, 5)]
+    fn test_replacement(#[case] strategy: ReplacementStrategy, #[case] expected: Score) {
+        let mut table = TranspositionTable::with_capacity(8, strategy);
+        let hashes = colliding_hashes(&table, 2);
+        table.insert(hashes[0], entry(5, 4, Bound::Exact));
+        table.insert(hashes[1], entry(9, 2, Bound::Exact));
+        let kept = table.peek(hashes[0]).or(table.peek(hashes[1])).unwrap();
+        assert_eq!(kept.score, expected);
+        assert_eq!(table.len(), 1);
+    }
+
+    #[test]
+    fn test_two_tier() {
+        let mut table = TranspositionTable::with_capacity(8, ReplacementStrategy::TwoTier);
+        let hashes = colliding_hashes(&table, 3);
+        table.insert(hashes[0], entry(1, 4, Bound::Exact));
+        table.insert(hashes[1], entry(2, 2, Bound::Exact));
+        // the shallow entry goes to the always replace slot
+        assert_eq!(table.peek(hashes[0]).unwrap().score, 1);
+        assert_eq!(table.peek(hashes[1]).unwrap().score, 2);
+        table.insert(hashes[2], entry(3, 1, Bound::Exact));
+        assert_eq!(table.peek(hashes[0]).unwrap().score, 1);
+        assert!(table.peek(hashes[1]).is_none());
+        assert_eq!(table.peek(hashes[2]).unwrap().score, 3);
+        assert_eq!(table.len(), 2);
+        assert_eq!(table.stats().overwrites, 1);
+    }
+
+    #[test]
+    fn test_two_tier_updates_always_replace_slot() {
+        let mut table = TranspositionTable::with_capacity(8, ReplacementStrategy::TwoTier);
+        let hashes = colliding_hashes(&table, 2);
+        table.insert(hashes[0], entry(1, 4, Bound::Exact));
+        table.insert(hashes[1], entry(2, 2, Bound::Exact));
+        // shallower than before, but that slot takes anything
+        table.insert(hashes[1], entry(3, 1, Bound::Exact));
+        assert_eq!(table.peek(hashes[0]).unwrap().score, 1);
+        assert_eq!(table.peek(hashes[1]).unwrap().score, 3);
+        assert_eq!(table.len(), 2);
+        assert_eq!(table.stats().overwrites, 0);
+    }
+
+    #[test]
+    fn test_two_tier_demotes_shallow_update() {
+        let mut table = TranspositionTable::with_capacity(8, ReplacementStrategy::TwoTier);
+        let hashes = colliding_hashes(&table, 2);
+        let first = table.bucket_start(hashes[0]);
+        table.insert(hashes[0], entry(1, 4, Bound::Exact));
+        table.insert(hashes[1], entry(2, 2, Bound::Exact));
+        table.insert(hashes[0], entry(3, 1, Bound::Exact));
+        // the deep entry stays where it was, the update takes the other slot
+        assert_eq!(table.peek(hashes[0]).unwrap().score, 1);
+        let demoted = table.slots[first + 1].as_ref().unwrap();
+        assert_eq!((demoted.hash, demoted.entry.score), (hashes[0], 3));
+        assert!(table.peek(hashes[1]).is_none());
+        assert_eq!(table.len(), 2);
+        assert_eq!(table.stats().overwrites, 1);
+        // further updates of the demoted copy don't count as overwrites
+        table.insert(hashes[0], entry(4, 1, Bound::Exact));
+        assert_eq!(table.slots[first + 1].as_ref().unwrap().entry.score, 4);
+        assert_eq!(table.stats().overwrites, 1);
+    }
+
+    #[test]
+    fn test_aging() {
+        let mut table = TranspositionTable::with_capacity(8, ReplacementStrategy::DepthPreferred);
+        let hashes = colliding_hashes(&table, 2);
+        table.insert(hashes[0], entry(1, 4, Bound::Exact));
+        table.insert(hashes[1], entry(2, 2, Bound::Exact));
+        assert!(table.peek(hashes[1]).is_none());
+        // deep entries left over from older searches make room
+        table.new_search();
+        table.insert(hashes[1], entry(2, 2, Bound::Exact));
+        assert_eq!(table.peek(hashes[1]).unwrap().score, 2);
+    }
+
+    #[test]
+    fn test_stays_bounded() {
+        let mut table = TranspositionTable::with_capacity(16, ReplacementStrategy::AlwaysReplace);
+        for hash in 0..100 {
+            table.insert(hash, entry(0, 1, Bound::Exact));
+        }
+        assert!(table.len() <= 16);
+        table.get(99);
+        table.get(1000);
+        let stats = table.stats();
+        assert_eq!((stats.hits, stats.misses), (1, 1));
+        assert_eq!(stats.overwrites as usize, 100 - table.len());
+        assert_eq!(stats.capacity, 16);
+    }
+}
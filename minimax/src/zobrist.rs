@@ -0,0 +1,134 @@
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{game::*, minimax::*};
+
+/// Hash types that can hold Zobrist keys. Arrays give hashes wider than u128
+pub trait ZobristKey: HashKey {
+    const ZERO: Self;
+
+    fn random(rng: &mut StdRng) -> Self;
+    fn xor(self, other: Self) -> Self;
+}
+
+impl ZobristKey for u64 {
+    const ZERO: Self = 0;
+
+    fn random(rng: &mut StdRng) -> Self {
+        rng.gen()
+    }
+
+    fn xor(self, other: Self) -> Self {
+        self ^ other
+    }
+}
+
+impl ZobristKey for u128 {
+    const ZERO: Self = 0;
+
+    fn random(rng: &mut StdRng) -> Self {
+        rng.gen()
+    }
+
+    fn xor(self, other: Self) -> Self {
+        self ^ other
+    }
+}
+
+impl<const N: usize> ZobristKey for [u128; N] {
+    const ZERO: Self = [0; N];
+
+    fn random(rng: &mut StdRng) -> Self {
+        std::array::from_fn(|_| rng.gen())
+    }
+
+    fn xor(self, other: Self) -> Self {
+        std::array::from_fn(|i| self[i] ^ other[i])
+    }
+}
+
+/// Random keys for every piece on every cell, see https://www.chessprogramming.org/Zobrist_Hashing.
+/// Placing or removing a piece xors its key into the hash, so moves update it in constant time
+pub struct ZobristTable<H> {
+    pieces: usize,
+    keys: Vec<H>,
+    side_to_move: H,
+}
+
+impl<H: ZobristKey> ZobristTable<H> {
+    /// The same seed always gives the same keys
+    pub fn new(cells: usize, pieces: usize, seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        Self {
+            pieces,
+            keys: (0..cells * pieces).map(|_| H::random(&mut rng)).collect(),
+            side_to_move: H::random(&mut rng),
+        }
+    }
+
+    /// Key to xor in when the piece gets placed on the cell, and out when it gets removed
+    pub fn piece(&self, cell: usize, piece: usize) -> H {
+        self.keys[cell * self.pieces + piece]
+    }
+
+    /// Key to xor every time the turn passes to the other player
+    pub fn side_to_move(&self) -> H {
+        self.side_to_move
+    }
+
+    /// Key of the player's piece on the cell. Empty cells don't change the hash
+    pub fn player(&self, cell: usize, player: Player) -> H {
+        match player {
+            Player::X => self.piece(cell, 0),
+            Player::O => self.piece(cell, 1),
+            Player::None => H::ZERO,
+        }
+    }
+
+    /// Full hash of a board, to start from before updating it incrementally
+    pub fn hash_board(&self, board: &[Player], current_player: Player) -> H {
+        let hash = board
+            .iter()
+            .enumerate()
+            .fold(H::ZERO, |hash, (cell, player)| {
+                hash.xor(self.player(cell, *player))
+            });
+        match current_player {
+            Player::O => hash.xor(self.side_to_move),
+            _ => hash,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_same_seed_same_keys() {
+        let a = ZobristTable::<u64>::new(9, 2, 42);
+        let b = ZobristTable::<u64>::new(9, 2, 42);
+        let c = ZobristTable::<u64>::new(9, 2, 43);
+        assert_eq!(a.piece(4, 1), b.piece(4, 1));
+        assert_eq!(a.side_to_move(), b.side_to_move());
+        assert_ne!(a.piece(4, 1), c.piece(4, 1));
+    }
+
+    #[test]
+    fn test_incremental_matches_full_hash() {
+        let table = ZobristTable::<[u128; 2]>::new(9, 2, 7);
+        let mut board = [Player::None; 9];
+        let empty = table.hash_board(&board, Player::X);
+        assert_eq!(empty, <[u128; 2]>::ZERO);
+
+        board[4] = Player::X;
+        let incremental = empty
+            .xor(table.player(4, Player::X))
+            .xor(table.side_to_move());
+        assert_eq!(incremental, table.hash_board(&board, Player::O));
+        // taking the move back gives the original hash
+        let undone = incremental
+            .xor(table.player(4, Player::X))
+            .xor(table.side_to_move());
+        assert_eq!(undone, empty);
+    }
+}
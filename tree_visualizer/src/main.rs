@@ -18,13 +18,17 @@ fn main() -> std::io::Result<()> {
     // .O.
     // ..X";
     // let game = minimax::tictactoe::TicTacToeGame::from_state(state, Player::O);
-    // let mut minimax = Minimax::new(MinimaxParams::default());
+    // let mut minimax = Minimax::new(MinimaxParams {
+    //     build_tree: true,
+    //     ..Default::default()
+    // });
 
     // connect 4 setup
     const MAX_DEPTH: i32 = 3;
     const ALTERNATIVES_TO_DRAW: usize = 10;
     let mut minimax = Minimax::new(MinimaxParams {
         max_depth: 5,
+        build_tree: true,
         ..Default::default()
     });
     let state = "
@@ -36,27 +40,26 @@ fn main() -> std::io::Result<()> {
     O O O X X O O";
     let game = minimax::connect4::Connect4Game::from_state(state, None, Player::X);
 
+    minimax.add_progress_listener(|progress| info!("Search progress: {}", progress));
+
     // get the decision tree
-    let decision_tree = minimax.minimax(&game);
+    let decision_tree = minimax.minimax(&game).tree.unwrap();
 
     // build the graph
     let mut graph = make_graph();
     graph_tree(
         &mut graph,
         decision_tree,
-        Box::new(game),
+        game,
         MAX_DEPTH,
         ALTERNATIVES_TO_DRAW,
     );
 
-    match minimax.get_internal_stats() {
-        (total, last, cache) => {
-            info!(
-                "Stats: nodes_examined_total={}, nodes_examined_last_run={}, cache_size={}",
-                total, last, cache
-            );
-        }
-    }
+    let (total, last, cache) = minimax.get_internal_stats();
+    info!(
+        "Stats: nodes_examined_total={}, nodes_examined_last_run={}, cache_size={}",
+        total, last, cache
+    );
 
     // print it to string
     let mut printer_context = PrinterContext::default();
@@ -72,10 +75,10 @@ fn main() -> std::io::Result<()> {
     write!(output_file, "{}", graph_svg)
 }
 
-fn graph_tree(
+fn graph_tree<G: MinimaxDriver>(
     graph: &mut Graph,
-    decision_tree: Rc<minimax::minimax::DecisionTreeNode>,
-    game: Box<dyn MinimaxDriver>,
+    decision_tree: Rc<minimax::minimax::DecisionTreeNode<G::Move>>,
+    game: G,
     max_depth: i32,
     alternatives_to_draw: usize,
 ) {
@@ -91,10 +94,11 @@ fn graph_tree(
     );
 }
 
-fn graph_node(
+#[allow(clippy::too_many_arguments)]
+fn graph_node<G: MinimaxDriver>(
     graph: &mut Graph,
-    decision_tree: Rc<minimax::minimax::DecisionTreeNode>,
-    game: Box<dyn MinimaxDriver>,
+    decision_tree: Rc<minimax::minimax::DecisionTreeNode<G::Move>>,
+    game: G,
     depth: i32,
     max_depth: i32,
     node_id: &mut i32,
@@ -121,18 +125,16 @@ fn graph_node(
         color_node.into(),
     );
 
-    if decision_tree.moves.len() == 0 {
+    if decision_tree.moves.is_empty() {
         return Some(current_node);
     }
 
     // prepare moves to iterate over
-    let score_factor = game.get_current_player().score_multiplier();
-    let mut all_moves: Vec<(Move, Rc<DecisionTreeNode>)> = decision_tree
+    let mut all_moves: Vec<(G::Move, NodeType<G::Move>)> = decision_tree
         .moves
         .iter()
-        .map(|(p, t)| (p.clone(), t.clone()))
+        .map(|(p, t)| (*p, t.clone()))
         .collect();
-    // all_moves.sort_by_key(|(_, n)| -n.estimate * score_factor); // already sorted by eval function, not needed anymore
     all_moves.sort_by_key(|(_, n)| n.visit_order); // order nodes by their visit order
     
     // always get best move in the front of the list
@@ -212,10 +214,7 @@ fn make_graph() -> Graph {
 }
 
 fn add_edge(graph: &mut Graph, node1: NodeId, node2: NodeId, color: String, is_heavy: bool) {
-    let mut attrs = vec![Attribute(
-        Id::Plain("color".into()),
-        Id::Plain(color.into()),
-    )];
+    let mut attrs = vec![Attribute(Id::Plain("color".into()), Id::Plain(color))];
     if is_heavy {
         attrs.push(Attribute(
             Id::Plain("weight".into()),
@@ -243,7 +242,7 @@ fn add_node(graph: &mut Graph, node_id: String, label: String, color: String) ->
                 Id::Escaped(format!("\"{}\"", label)),
             ),
             Attribute(Id::Plain("shape".into()), Id::Plain("box".into())),
-            Attribute(Id::Plain("color".into()), Id::Plain(color.into())),
+            Attribute(Id::Plain("color".into()), Id::Plain(color)),
         ],
     );
     graph.add_stmt(Stmt::Node(node));